@@ -1,13 +1,33 @@
-
 precision mediump float;
 
+#ifdef VERTEX_COLOR
 varying vec4 v_color;
-varying vec2 v_texCoords;
+#endif
 
+#ifdef TEXTURED
+varying vec2 v_texCoords;
 uniform sampler2D u_tex0;
+#endif
+
+#ifdef EDGE_AA
+varying float v_edge;
+#endif
 
 void main() 
 {
-    vec4 color = texture2D(u_tex0, v_texCoords);
+    vec4 color = vec4(1.0);
+
+#ifdef TEXTURED
+    color = texture2D(u_tex0, v_texCoords);
+#endif
+
+#ifdef VERTEX_COLOR
+    color *= v_color;
+#endif
+
+#ifdef EDGE_AA
+    color.a *= smoothstep(0.0, 1.0, v_edge);
+#endif
+
     gl_FragColor = color;
-}
\ No newline at end of file
+}
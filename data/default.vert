@@ -1,22 +1,36 @@
+#include "projection.glsl"
 
-attribute vec4  a_color;
 attribute vec4  a_vertex;
-attribute vec2  a_texCoord;
 
+#ifdef VERTEX_COLOR
+attribute vec4  a_color;
 varying vec4    v_color;
+#endif
+
+#ifdef TEXTURED
+attribute vec2  a_texCoord;
 varying vec2    v_texCoords;
+#endif
+
+#ifdef EDGE_AA
+// coverage of the anti-aliased fringe of tessellated shapes
+attribute float a_edge;
+varying float   v_edge;
+#endif
 
 void main() 
 {
-    vec2 screenSize = vec2(1024, 600);
-
-    // divide to get from pixels to 0-1 scale
-    vec4 transformedPos = a_vertex / vec4(screenSize.x, screenSize.y, 1.0, 1.0);
-    transformedPos.y = transformedPos.y * -1.0;transformedPos.y;
-    // offset the pos to match opengl coordinates ( with (0,0) at the center)
-    transformedPos += vec4(-1.0, 1.0, 0.0, 0.0);
-        
-    gl_Position = transformedPos;
+    gl_Position = ProjectToScreen(a_vertex);
+
+#ifdef VERTEX_COLOR
     v_color = a_color;
+#endif
+
+#ifdef TEXTURED
     v_texCoords = a_texCoord;
-}
\ No newline at end of file
+#endif
+
+#ifdef EDGE_AA
+    v_edge = a_edge;
+#endif
+}
@@ -0,0 +1,19 @@
+// Shared projection from UI pixel coordinates to clip space
+
+const vec2 screenSize = vec2(1024, 600);
+
+// offset of the render pass in pixels, e.g. while a page slides in
+uniform vec2 u_offset;
+
+vec4 ProjectToScreen(vec4 pixelPos)
+{
+    pixelPos.xy += u_offset;
+
+    // divide to get from pixels to 0-1 scale
+    vec4 transformedPos = pixelPos / vec4(screenSize.x, screenSize.y, 1.0, 1.0);
+    transformedPos.y = transformedPos.y * -1.0;
+    // offset the pos to match opengl coordinates ( with (0,0) at the center)
+    transformedPos += vec4(-1.0, 1.0, 0.0, 0.0);
+
+    return transformedPos;
+}
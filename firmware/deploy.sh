@@ -3,6 +3,22 @@
 USER=root
 DEVICE_NAME=dis-proto
 TARGET_PATH=/opt/firmware
+STAGING_PATH=/tmp/firmware-staging
 
-scp -p ./target/armv7-unknown-linux-gnueabihf/debug/firmware $USER@$DEVICE_NAME:$TARGET_PATH/firmware
-scp -pr ./../data $USER@$DEVICE_NAME:$TARGET_PATH
+# stage the new build and check the shaders on the device before replacing the installed firmware
+ssh $USER@$DEVICE_NAME "rm -rf $STAGING_PATH && mkdir -p $STAGING_PATH"
+scp -p ./target/armv7-unknown-linux-gnueabihf/debug/firmware $USER@$DEVICE_NAME:$STAGING_PATH/firmware
+scp -pr ./../data $USER@$DEVICE_NAME:$STAGING_PATH
+
+if ! ssh $USER@$DEVICE_NAME "$STAGING_PATH/firmware --validate-shaders $STAGING_PATH/data"; then
+    echo "Shader validation failed, firmware not deployed"
+    exit 1
+fi
+
+# decoded images and linked programs load faster at boot, a missing cache
+# only costs boot time
+if ! ssh $USER@$DEVICE_NAME "$STAGING_PATH/firmware --build-asset-cache $STAGING_PATH/data"; then
+    echo "Building the asset cache failed, assets will be loaded from source at boot"
+fi
+
+ssh $USER@$DEVICE_NAME "cp -pr $STAGING_PATH/* $TARGET_PATH/"
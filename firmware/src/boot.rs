@@ -0,0 +1,38 @@
+#![allow(non_snake_case)]
+
+use std::fs;
+use std::time::Instant;
+
+// Logs the duration of the startup phases, to measure how far we are from
+// the boot time target. Times are relative to the start of the process and,
+// where available, to the kernel start (/proc/uptime).
+pub struct BootLog {
+    start : Instant,
+    startUptime : Option<f32>,
+}
+
+impl BootLog {
+
+    pub fn new() -> BootLog {
+        BootLog {
+            start: Instant::now(),
+            startUptime: ReadUptime(),
+        }
+    }
+
+    pub fn Mark(& self, phase : & str) {
+        let elapsed = self.start.elapsed();
+        let sinceStart = elapsed.as_secs() * 1000 + elapsed.subsec_millis() as u64;
+
+        match self.startUptime {
+            Some(uptime) => println!("Boot: {:<20} +{}ms (since kernel start {:.2}s)", phase, sinceStart, uptime + sinceStart as f32 / 1000.0),
+            None => println!("Boot: {:<20} +{}ms", phase, sinceStart),
+        }
+    }
+}
+
+// Seconds since the kernel started
+fn ReadUptime() -> Option<f32> {
+    let content = fs::read_to_string("/proc/uptime").ok()?;
+    content.split_whitespace().next()?.parse().ok()
+}
@@ -0,0 +1,180 @@
+#![deny(unsafe_code)]
+#![allow(non_snake_case)]
+
+use std::fs::File;
+use std::io::{BufReader,BufRead};
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+use ebola::backlight;
+use ebola::input;
+
+use vehicle::VehicleData;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DayPhase {
+    Day,
+    Night,
+}
+
+// what decides between the day and night profile
+#[derive(Clone, Copy, PartialEq)]
+pub enum PhaseSource {
+    // fixed hours of the day
+    Time,
+    // ambient light sensor, falls back to the time without a reading
+    AmbientLight,
+    // night while the headlight (low or full beam) is on
+    Headlight,
+}
+
+pub struct DisplayConfiguration
+{
+    // sysfs backlight directory
+    pub backlightPath : String,
+    // brightness of the day and night profile, 0..1
+    pub dayLevel : f32,
+    pub nightLevel : f32,
+    // duration of the transition between the profiles in s, used for the
+    // brightness ramp and the palette cross-fade
+    pub rampTime : f32,
+    pub phaseSource : PhaseSource,
+    // local time in hours at which day and night start
+    pub dayStart : f32,
+    pub nightStart : f32,
+    // offset of the local time to UTC in hours, the Pi has no timezone set
+    pub utcOffset : f32,
+    // ambient light in lux below which it's night, it must rise above
+    // threshold + hysteresis to be day again
+    pub ambientThreshold : f32,
+    pub ambientHysteresis : f32,
+    // palette of the UI, 'Auto' switches between 'day' and 'night' with
+    // the day phase
+    pub theme : String,
+    // evdev device of the touch screen
+    pub touchDevice : String,
+    // largest coordinates the touch screen reports, None if it reports
+    // screen pixels
+    pub touchSize : Option<[f32; 2]>,
+    // km/h above which only the riding page is shown and gestures are ignored
+    pub pageGuardSpeed : f32,
+}
+
+impl DisplayConfiguration {
+    pub fn new() -> DisplayConfiguration {
+        DisplayConfiguration {
+            backlightPath: backlight::DEFAULT_BACKLIGHT_PATH.to_owned(),
+            dayLevel: 1.0,
+            nightLevel: 0.3,
+            rampTime: 2.0,
+            phaseSource: PhaseSource::Time,
+            dayStart: 7.0,
+            nightStart: 19.0,
+            utcOffset: 0.0,
+            ambientThreshold: 50.0,
+            ambientHysteresis: 20.0,
+            theme: "Auto".to_owned(),
+            touchDevice: input::DEFAULT_TOUCH_DEVICE.to_owned(),
+            touchSize: None,
+            pageGuardSpeed: 10.0,
+        }
+    }
+}
+
+pub fn LoadDisplayConfiguration(filePath : & str) -> DisplayConfiguration {
+    
+    let fileHndl = File::open(filePath).unwrap();
+    let reader = BufReader::new(& fileHndl);
+
+    let mut config = DisplayConfiguration::new();
+
+    for line in reader.lines() {
+        let l = line.unwrap();
+
+        if l.starts_with("#") || l.trim().len() == 0 {
+            continue;
+        }
+
+        let confPair : Vec<_> = l.split("=").collect();
+
+        if confPair.len() != 2 {
+            panic!("Error while reading display configuration from '{}'. Line '{} is not a valid configuration pair. 'Key = Value' expected.", filePath, l);
+        }
+
+        let value = confPair[1].trim();
+
+        match confPair[0].trim() {
+            "BacklightPath" => config.backlightPath = value.to_owned(),
+            "DayLevel" => config.dayLevel = value.parse::<f32>().unwrap(),
+            "NightLevel" => config.nightLevel = value.parse::<f32>().unwrap(),
+            "RampTime" => config.rampTime = value.parse::<f32>().unwrap(),
+            "PhaseSource" => config.phaseSource = match value {
+                "Time" => PhaseSource::Time,
+                "AmbientLight" => PhaseSource::AmbientLight,
+                "Headlight" => PhaseSource::Headlight,
+                _ => panic!("Error while reading display configuration from '{}'. Unknown phase source '{}', expected Time, AmbientLight or Headlight.", filePath, value),
+            },
+            "DayStart" => config.dayStart = value.parse::<f32>().unwrap(),
+            "NightStart" => config.nightStart = value.parse::<f32>().unwrap(),
+            "UtcOffset" => config.utcOffset = value.parse::<f32>().unwrap(),
+            "AmbientThreshold" => config.ambientThreshold = value.parse::<f32>().unwrap(),
+            "AmbientHysteresis" => config.ambientHysteresis = value.parse::<f32>().unwrap(),
+            "Theme" => config.theme = value.to_owned(),
+            "TouchDevice" => config.touchDevice = value.to_owned(),
+            "TouchSize" => config.touchSize = match value {
+                "Screen" => None,
+                _ => {
+                    let size : Vec<f32> = value.split('x').map(|v| v.trim().parse::<f32>().unwrap()).collect();
+                    if size.len() != 2 {
+                        panic!("Error while reading display configuration from '{}'. Invalid touch size '{}', expected Screen or <width>x<height>.", filePath, value);
+                    }
+                    Some([size[0], size[1]])
+                }
+            },
+            "PageGuardSpeed" => config.pageGuardSpeed = value.parse::<f32>().unwrap(),
+            _  => println!("Error while reading display configuration from '{}'. Unknown key '{}' found.'", filePath, confPair[0]),
+        }
+    }
+
+    config
+}
+
+// Local time of day in hours
+pub fn LocalHour(config : & DisplayConfiguration, now : SystemTime) -> f32 {
+    // f32 can't hold the seconds since the epoch to the minute, only the
+    // time of day is converted
+    let secondsOfDay = now.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) % 86400;
+    let hour = (secondsOfDay as f32 / 3600.0 + config.utcOffset) % 24.0;
+
+    if hour < 0.0 { hour + 24.0 } else { hour }
+}
+
+fn PhaseByTime(config : & DisplayConfiguration, hour : f32) -> DayPhase {
+    let isDay = if config.dayStart <= config.nightStart {
+        hour >= config.dayStart && hour < config.nightStart
+    } else {
+        hour >= config.dayStart || hour < config.nightStart
+    };
+
+    if isDay { DayPhase::Day } else { DayPhase::Night }
+}
+
+// Decides the phase from the configured source. The current phase is
+// needed for the hysteresis of the ambient light.
+pub fn SelectDayPhase(config : & DisplayConfiguration, data : & VehicleData, hour : f32, current : DayPhase) -> DayPhase {
+    match config.phaseSource {
+        PhaseSource::Time => PhaseByTime(config, hour),
+        PhaseSource::AmbientLight => match data.ambientLight {
+            Some(lux) if current == DayPhase::Day => if lux < config.ambientThreshold { DayPhase::Night } else { DayPhase::Day },
+            Some(lux) => if lux > config.ambientThreshold + config.ambientHysteresis { DayPhase::Day } else { DayPhase::Night },
+            None => PhaseByTime(config, hour),
+        },
+        PhaseSource::Headlight => if data.fullBeamActive || data.lowBeamActive { DayPhase::Night } else { DayPhase::Day },
+    }
+}
+
+pub fn BacklightLevel(config : & DisplayConfiguration, phase : DayPhase) -> f32 {
+    match phase {
+        DayPhase::Day => config.dayLevel,
+        DayPhase::Night => config.nightLevel,
+    }
+}
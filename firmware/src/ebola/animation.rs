@@ -0,0 +1,426 @@
+#![allow(non_snake_case)]
+
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use duration::Seconds;
+
+// Everything in here is advanced with the delta time of the main loop, so
+// animations run at the same speed no matter how often frames are rendered.
+
+////////////////////////////////////
+// Easing
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Easing {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    SineInOut,
+    // overshoots the end a bit before settling, for pop-ups
+    BackOut,
+}
+
+// Maps the linear progress t (0..1) to the eased progress. Stays 0 at the
+// start and 1 at the end, BackOut leaves 0..1 in between.
+pub fn Ease(easing : Easing, t : f32) -> f32 {
+
+    let t = t.max(0.0).min(1.0);
+
+    match easing {
+        Easing::Linear => t,
+        Easing::QuadIn => t * t,
+        Easing::QuadOut => t * (2.0 - t),
+        Easing::QuadInOut => if t < 0.5 { 2.0 * t * t } else { -1.0 + (4.0 - 2.0 * t) * t },
+        Easing::CubicIn => t * t * t,
+        Easing::CubicOut => { let f = t - 1.0; f * f * f + 1.0 },
+        Easing::CubicInOut => if t < 0.5 { 4.0 * t * t * t } else { let f = 2.0 * t - 2.0; 0.5 * f * f * f + 1.0 },
+        Easing::SineInOut => 0.5 * (1.0 - (PI * t).cos()),
+        Easing::BackOut => {
+            let s = 1.70158;
+            let f = t - 1.0;
+            f * f * ((s + 1.0) * f + s) + 1.0
+        },
+    }
+}
+
+pub fn Lerp(from : f32, to : f32, t : f32) -> f32 {
+    from + (to - from) * t
+}
+
+////////////////////////////////////
+// Tween
+
+// Moves a value from one end to the other in a fixed time.
+pub struct Tween {
+    from : f32,
+    to : f32,
+    easing : Easing,
+    duration : f32,
+    elapsed : f32,
+}
+
+impl Tween {
+
+    pub fn new(from : f32, to : f32, duration : Duration, easing : Easing) -> Tween {
+        Tween {
+            from: from,
+            to: to,
+            easing: easing,
+            duration: Seconds(duration),
+            elapsed: 0.0,
+        }
+    }
+
+    // A finished tween resting at the value
+    pub fn Constant(value : f32) -> Tween {
+        Tween::new(value, value, Duration::from_secs(0), Easing::Linear)
+    }
+
+    // Starts a new tween from the current value, so retargeting a running
+    // tween doesn't jump.
+    pub fn Retarget(& mut self, to : f32, duration : Duration) {
+        self.from = self.Value();
+        self.to = to;
+        self.duration = Seconds(duration);
+        self.elapsed = 0.0;
+    }
+
+    // Returns whether the value changed.
+    pub fn Update(& mut self, dt : Duration) -> bool {
+        if self.IsFinished() {
+            return false;
+        }
+
+        self.elapsed = (self.elapsed + Seconds(dt)).min(self.duration);
+        true
+    }
+
+    pub fn Value(& self) -> f32 {
+        if self.duration <= 0.0 {
+            return self.to;
+        }
+        Lerp(self.from, self.to, Ease(self.easing, self.elapsed / self.duration))
+    }
+
+    pub fn Target(& self) -> f32 {
+        self.to
+    }
+
+    pub fn IsFinished(& self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+////////////////////////////////////
+// Smoothing
+
+// below this distance and speed a smoother or spring counts as settled
+const SETTLE_EPSILON : f32 = 0.001;
+
+// Follows a target with critical damping: as fast as possible without
+// overshooting. Suited for needles fed with noisy telemetry, a new sample
+// changes the target without a visible jump.
+pub struct Smoother {
+    value : f32,
+    velocity : f32,
+    target : f32,
+    // roughly the time to reach the target
+    smoothTime : f32,
+    // units per second, None is unlimited
+    maxSpeed : Option<f32>,
+}
+
+impl Smoother {
+
+    pub fn new(value : f32, smoothTime : Duration) -> Smoother {
+        Smoother {
+            value: value,
+            velocity: 0.0,
+            target: value,
+            smoothTime: Seconds(smoothTime).max(0.0001),
+            maxSpeed: None,
+        }
+    }
+
+    pub fn WithMaxSpeed(mut self, maxSpeed : f32) -> Smoother {
+        self.maxSpeed = Some(maxSpeed);
+        self
+    }
+
+    pub fn SetTarget(& mut self, target : f32) {
+        self.target = target;
+    }
+
+    // Jumps to the value without animation
+    pub fn Snap(& mut self, value : f32) {
+        self.value = value;
+        self.target = value;
+        self.velocity = 0.0;
+    }
+
+    // Returns whether the value changed. Uses the closed form approximation
+    // from Game Programming Gems 4, stable for any dt.
+    pub fn Update(& mut self, dt : Duration) -> bool {
+
+        if self.IsSettled() {
+            self.value = self.target;
+            self.velocity = 0.0;
+            return false;
+        }
+
+        let dt = Seconds(dt);
+        let omega = 2.0 / self.smoothTime;
+        let x = omega * dt;
+        let decay = 1.0 / (1.0 + x + 0.48 * x * x + 0.235 * x * x * x);
+
+        let mut change = self.value - self.target;
+        if let Some(maxSpeed) = self.maxSpeed {
+            let maxChange = maxSpeed * self.smoothTime;
+            change = change.max(-maxChange).min(maxChange);
+        }
+        let target = self.value - change;
+
+        let temp = (self.velocity + omega * change) * dt;
+        self.velocity = (self.velocity - omega * temp) * decay;
+        let mut value = target + (change + temp) * decay;
+
+        // don't overshoot the real target
+        if (self.target - self.value > 0.0) == (value > self.target) {
+            value = self.target;
+            self.velocity = 0.0;
+        }
+
+        self.value = value;
+        true
+    }
+
+    pub fn Value(& self) -> f32 {
+        self.value
+    }
+
+    pub fn IsSettled(& self) -> bool {
+        (self.value - self.target).abs() < SETTLE_EPSILON && self.velocity.abs() < SETTLE_EPSILON
+    }
+}
+
+// integration step of the spring, small enough to stay stable for stiff
+// springs at low frame rates
+const SPRING_STEP : f32 = 1.0 / 240.0;
+
+// Damped spring towards a target. A damping ratio below 1 overshoots and
+// wobbles like a mechanical needle, 1 is critically damped.
+pub struct Spring {
+    value : f32,
+    velocity : f32,
+    target : f32,
+    // angular frequency in rad/s
+    omega : f32,
+    dampingRatio : f32,
+}
+
+impl Spring {
+
+    // frequency in Hz of the undamped oscillation
+    pub fn new(value : f32, frequency : f32, dampingRatio : f32) -> Spring {
+        Spring {
+            value: value,
+            velocity: 0.0,
+            target: value,
+            omega: 2.0 * PI * frequency,
+            dampingRatio: dampingRatio,
+        }
+    }
+
+    pub fn SetTarget(& mut self, target : f32) {
+        self.target = target;
+    }
+
+    pub fn Snap(& mut self, value : f32) {
+        self.value = value;
+        self.target = value;
+        self.velocity = 0.0;
+    }
+
+    // Returns whether the value changed.
+    pub fn Update(& mut self, dt : Duration) -> bool {
+
+        if self.IsSettled() {
+            self.value = self.target;
+            self.velocity = 0.0;
+            return false;
+        }
+
+        let mut remaining = Seconds(dt);
+        while remaining > 0.0 {
+            let step = remaining.min(SPRING_STEP);
+            let acceleration = -self.omega * self.omega * (self.value - self.target)
+                               - 2.0 * self.dampingRatio * self.omega * self.velocity;
+
+            // semi-implicit Euler
+            self.velocity += acceleration * step;
+            self.value += self.velocity * step;
+            remaining -= step;
+        }
+
+        true
+    }
+
+    pub fn Value(& self) -> f32 {
+        self.value
+    }
+
+    pub fn IsSettled(& self) -> bool {
+        (self.value - self.target).abs() < SETTLE_EPSILON && self.velocity.abs() < SETTLE_EPSILON
+    }
+}
+
+////////////////////////////////////
+// Timeline
+
+struct Segment {
+    start : f32,
+    length : f32,
+    from : f32,
+    to : f32,
+    easing : Easing,
+}
+
+struct Track {
+    name : String,
+    // sorted by start
+    segments : Vec<Segment>,
+}
+
+// Several named values animated along one time axis, e.g. the offset and
+// opacity of a pop-up or the positions of two pages during a page change.
+// Played backwards it undoes the transition from wherever it currently is.
+pub struct Timeline {
+    tracks : Vec<Track>,
+    time : f32,
+    duration : f32,
+    // 1 plays forward, -1 backwards
+    direction : f32,
+    playing : bool,
+}
+
+impl Timeline {
+
+    pub fn new() -> Timeline {
+        Timeline {
+            tracks: vec![],
+            time: 0.0,
+            duration: 0.0,
+            direction: 1.0,
+            playing: false,
+        }
+    }
+
+    // Animates the track from 'from' to 'to' between start and start +
+    // length. Before its first segment a track has the first segment's
+    // start value, between segments the end value of the previous one.
+    pub fn WithSegment(mut self, track : & str, start : Duration, length : Duration, from : f32, to : f32, easing : Easing) -> Timeline {
+
+        let segment = Segment {
+            start: Seconds(start),
+            length: Seconds(length),
+            from: from,
+            to: to,
+            easing: easing,
+        };
+
+        self.duration = self.duration.max(segment.start + segment.length);
+
+        match self.tracks.iter().position(|t| t.name == track) {
+            Some(idx) => {
+                let segments = & mut self.tracks[idx].segments;
+                let pos = segments.iter().position(|s| s.start > segment.start).unwrap_or(segments.len());
+                segments.insert(pos, segment);
+            },
+            None => self.tracks.push(Track { name: track.to_owned(), segments: vec![segment] }),
+        }
+
+        self
+    }
+
+    // Plays forward from the current position
+    pub fn Play(& mut self) {
+        self.direction = 1.0;
+        self.playing = true;
+    }
+
+    // Plays backwards from the current position
+    pub fn PlayReverse(& mut self) {
+        self.direction = -1.0;
+        self.playing = true;
+    }
+
+    pub fn Stop(& mut self) {
+        self.playing = false;
+    }
+
+    pub fn Seek(& mut self, time : Duration) {
+        self.time = Seconds(time).min(self.duration);
+    }
+
+    pub fn SeekToEnd(& mut self) {
+        self.time = self.duration;
+    }
+
+    // Returns whether the values changed. Stops at either end.
+    pub fn Update(& mut self, dt : Duration) -> bool {
+
+        if !self.playing {
+            return false;
+        }
+
+        self.time = (self.time + self.direction * Seconds(dt)).max(0.0).min(self.duration);
+
+        if (self.direction > 0.0 && self.time >= self.duration) || (self.direction < 0.0 && self.time <= 0.0) {
+            self.playing = false;
+        }
+
+        true
+    }
+
+    pub fn IsPlaying(& self) -> bool {
+        self.playing
+    }
+
+    pub fn AtStart(& self) -> bool {
+        self.time <= 0.0
+    }
+
+    pub fn AtEnd(& self) -> bool {
+        self.time >= self.duration
+    }
+
+    // Value of the track at the current time, 0 for unknown tracks.
+    pub fn Value(& self, track : & str) -> f32 {
+
+        let segments = match self.tracks.iter().find(|t| t.name == track) {
+            Some(track) => & track.segments,
+            None => return 0.0,
+        };
+
+        let mut value = match segments.first() {
+            Some(segment) => segment.from,
+            None => return 0.0,
+        };
+
+        for segment in segments.iter().take_while(|s| s.start <= self.time) {
+            let end = segment.start + segment.length;
+            value = if self.time >= end {
+                segment.to
+            } else {
+                Lerp(segment.from, segment.to, Ease(segment.easing, (self.time - segment.start) / segment.length))
+            };
+        }
+
+        value
+    }
+}
@@ -0,0 +1,209 @@
+#![allow(non_snake_case)]
+
+use std::collections::VecDeque;
+use std::io;
+use std::io::Write;
+use std::time::{ Duration, Instant };
+
+use opengles::glesv2 as gl;
+use videocore::bcm_host;
+
+use assets::AssetManager;
+use error::{ Error, Result };
+use input::{ Gesture, GestureRecognizer, TouchEvent, TouchScreen };
+use renderer;
+use renderer::RenderContext;
+use scheduler::FrameScheduler;
+use signals;
+use stats::RenderStats;
+use GLContext;
+
+pub enum Event {
+    // SIGINT/SIGTERM was received, the main loop exits after this frame
+    Quit,
+    // raw event of the touch screen
+    Touch(TouchEvent),
+    // tap or swipe recognized from the touch events
+    Gesture(Gesture),
+}
+
+// number of rendered frames RenderStats keeps
+const STATS_HISTORY_LENGTH : usize = 120;
+
+// Everything the application hooks work with
+pub struct AppContext {
+    pub render : RenderContext,
+    pub scheduler : FrameScheduler,
+    pub assets : AssetManager,
+    // statistics of the last rendered frames
+    pub stats : RenderStats,
+    // None without touch input
+    touch : Option<(TouchScreen, GestureRecognizer)>,
+    events : VecDeque<Event>,
+    quitRequested : bool,
+}
+
+impl AppContext {
+
+    pub fn new(render : RenderContext, scheduler : FrameScheduler, assets : AssetManager) -> AppContext {
+        AppContext {
+            render: render,
+            scheduler: scheduler,
+            assets: assets,
+            stats: RenderStats::new(STATS_HISTORY_LENGTH),
+            touch: None,
+            events: VecDeque::new(),
+            quitRequested: false,
+        }
+    }
+
+    // Touch and gesture events of the screen are queued every frame
+    pub fn SetTouchScreen(& mut self, touchScreen : TouchScreen, gestures : GestureRecognizer) {
+        self.touch = Some((touchScreen, gestures));
+    }
+
+    // Queues the events of the touch screen. A device that fails to read
+    // (e.g. unplugged) is dropped.
+    fn PollInput(& mut self) {
+
+        let touchEvents = match self.touch {
+            Some((ref mut touchScreen, _)) => touchScreen.Poll(),
+            None => return,
+        };
+
+        let touchEvents = match touchEvents {
+            Ok(touchEvents) => touchEvents,
+            Err(err) => {
+                println!("Touch input disabled. {}", err);
+                self.touch = None;
+                return;
+            }
+        };
+
+        for touchEvent in touchEvents {
+            self.events.push_back(Event::Touch(touchEvent));
+
+            if let Some((_, ref mut gestures)) = self.touch {
+                if let Some(gesture) = gestures.Feed(& touchEvent) {
+                    self.events.push_back(Event::Gesture(gesture));
+                }
+            }
+        }
+    }
+
+    // Queues an event, it's handed to the application at the next frame.
+    pub fn PushEvent(& mut self, event : Event) {
+        self.events.push_back(event);
+    }
+
+    // Leaves the main loop after the current frame.
+    pub fn Quit(& mut self) {
+        self.quitRequested = true;
+    }
+}
+
+pub trait Application {
+
+    // Called once before the first frame.
+    fn init(& mut self, ctx : & mut AppContext) -> Result<()>;
+
+    // Called every frame with the time passed since the previous one.
+    fn update(& mut self, ctx : & mut AppContext, dt : Duration);
+
+    fn handle_event(& mut self, _ctx : & mut AppContext, _event : & Event) {
+    }
+
+    // Called right before the render passes are drawn. Only called for
+    // frames the scheduler decided to render.
+    fn render(& mut self, _ctx : & mut AppContext) {
+    }
+
+    // Called once after the main loop exited, while the GL context is
+    // still alive.
+    fn shutdown(& mut self, _ctx : & mut AppContext) {
+    }
+}
+
+// Runs the application until it quits or the process receives SIGINT/SIGTERM.
+// Afterwards all GL objects and the EGL context are released in order.
+pub fn RunApplication<A : Application>(app : & mut A, glCtx : GLContext, mut ctx : AppContext) -> Result<()> {
+
+    signals::InstallShutdownHandler();
+
+    let screenRes = match bcm_host::graphics_get_display_size(0) {
+        Some(x) => x,
+        None => return Err(Error::Window("Failed to query the display size"))
+    };
+
+    let screenSize = (screenRes.width as i32, screenRes.height as i32);
+    gl::viewport(0, 0, screenSize.0, screenSize.1);
+
+    app.init(& mut ctx)?;
+
+    let mut stateCache = renderer::RenderStateCache::new();
+    // time since the last rendered frame, skipped frames included
+    let mut frameTime = Duration::from_secs(0);
+
+    while !ctx.quitRequested {
+
+        let deltaTime = ctx.scheduler.BeginFrame();
+        frameTime += deltaTime;
+
+        if signals::ShutdownRequested() {
+            ctx.PushEvent(Event::Quit);
+            ctx.Quit();
+        }
+
+        ctx.PollInput();
+
+        while let Some(event) = ctx.events.pop_front() {
+            app.handle_event(& mut ctx, & event);
+        }
+
+        let updateStart = Instant::now();
+        app.update(& mut ctx, deltaTime);
+        let updateTime = updateStart.elapsed();
+
+        if !ctx.scheduler.ShouldRender() {
+            continue;
+        }
+
+        let renderStart = Instant::now();
+        app.render(& mut ctx);
+
+        DrawFrame(& ctx.render, & mut stateCache);
+        let renderTime = renderStart.elapsed();
+
+        // blocks until the vertical blank with vsync on
+        let swapStart = Instant::now();
+        glCtx.SwapBuffers();
+        let swapTime = swapStart.elapsed();
+
+        ctx.scheduler.FrameRendered();
+        ctx.stats.EndFrame(frameTime, updateTime, renderTime, swapTime);
+        frameTime = Duration::from_secs(0);
+    }
+
+    println!("Shutting down");
+    app.shutdown(& mut ctx);
+
+    // GL objects have to be deleted before the context is destroyed
+    drop(ctx);
+    drop(glCtx);
+
+    let _ = io::stdout().flush();
+
+    Ok(())
+}
+
+// Clears the current surface and draws the passes. Used by the layer
+// threads as well.
+pub fn DrawFrame(render : & RenderContext, stateCache : & mut renderer::RenderStateCache) {
+    // a scissor rect left over from the last frame would limit the clear
+    stateCache.Apply(& renderer::RenderState::default());
+    let clearColor = render.clearColor;
+    gl::clear_color(clearColor[0], clearColor[1], clearColor[2], clearColor[3]);
+    gl::clear(gl::GL_COLOR_BUFFER_BIT);
+
+    render.Render(stateCache);
+}
@@ -0,0 +1,137 @@
+#![allow(non_snake_case)]
+
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Instant;
+
+use cache;
+use error::Result;
+use renderer;
+use renderer::{ ShaderStage, ShaderVariant };
+use texture;
+use texture::{ Texture, TextureOptions };
+
+// Loads shaders and textures relative to the data directory and caches them
+// by name, so every asset is only loaded once. Assets are handed out as
+// shared handles and stay alive as long as a handle or the manager holds them.
+pub struct AssetManager {
+    dataPath : String,
+    shaders : HashMap<String, Rc<ShaderStage>>,
+    textures : HashMap<String, Rc<Texture>>,
+    // content of the variant file, read on the first variant load
+    declaredVariants : Option<Vec<ShaderVariant>>,
+}
+
+impl AssetManager {
+
+    pub fn new(dataPath : & str) -> AssetManager {
+        AssetManager {
+            dataPath: dataPath.to_owned(),
+            shaders: HashMap::new(),
+            textures: HashMap::new(),
+            declaredVariants: None,
+        }
+    }
+
+    pub fn FullPath(& self, relPath : & str) -> String {
+        format!("{}/{}", self.dataPath, relPath)
+    }
+
+    // Shader stage by name, without the file extension (see LoadShaderStage)
+    pub fn GetShader(& mut self, name : & str) -> Result<Rc<ShaderStage>> {
+        self.GetShaderVariant(name, &[])
+    }
+
+    // Every combination of defines is compiled into its own program and
+    // cached separately. The order of the defines doesn't matter. A program
+    // binary of the asset cache is preferred over compiling the sources.
+    pub fn GetShaderVariant(& mut self, name : & str, defines : &[& str]) -> Result<Rc<ShaderStage>> {
+
+        let mut sortedDefines = defines.to_vec();
+        sortedDefines.sort();
+
+        let key = sortedDefines.iter().fold(name.to_owned(), |key, define| key + "#" + define);
+
+        if let Some(shader) = self.shaders.get(& key) {
+            return Ok(shader.clone());
+        }
+
+        if !sortedDefines.is_empty() && !self.IsDeclaredVariant(name, & sortedDefines) {
+            println!("Warning: shader variant {} {:?} is not listed in {}, it's not validated before deployment", name, sortedDefines, renderer::SHADER_VARIANTS_FILE);
+        }
+
+        let cachePath = cache::ProgramCachePath(& self.dataPath, name, & sortedDefines);
+        let shader = Rc::new(renderer::LoadShaderStageCached(& self.FullPath(name), & sortedDefines, Some(& cachePath))?);
+        self.shaders.insert(key, shader.clone());
+
+        Ok(shader)
+    }
+
+    // Whether the variant is listed in the data directory's variant file,
+    // which --validate-shaders goes through. Without the file nothing is.
+    fn IsDeclaredVariant(& mut self, name : & str, sortedDefines : &[& str]) -> bool {
+
+        if self.declaredVariants.is_none() {
+            // defines are sorted once, so the lookups compare directly
+            let mut variants = renderer::LoadShaderVariants(& self.FullPath(renderer::SHADER_VARIANTS_FILE)).unwrap_or_default();
+            for variant in variants.iter_mut() {
+                variant.defines.sort();
+            }
+            self.declaredVariants = Some(variants);
+        }
+
+        self.declaredVariants.as_ref().map_or(false, |variants| variants.iter().any(|variant| {
+            variant.name == name && variant.defines.iter().map(|define| define.as_str()).eq(sortedDefines.iter().cloned())
+        }))
+    }
+
+    pub fn GetTexture(& mut self, name : & str) -> Result<Rc<Texture>> {
+        self.GetTextureWithOptions(name, & TextureOptions::default())
+    }
+
+    // The options are only used when the texture is loaded the first time.
+    // An up to date entry of the asset cache is preferred over the source.
+    pub fn GetTextureWithOptions(& mut self, name : & str, options : & TextureOptions) -> Result<Rc<Texture>> {
+
+        if let Some(texture) = self.textures.get(name) {
+            return Ok(texture.clone());
+        }
+
+        let startTime = Instant::now();
+        let path = self.FullPath(name);
+
+        let data = cache::LoadTextureData(& path, & cache::CachePath(& self.dataPath, name), options)?;
+        let texture = Rc::new(texture::UploadTexture(& data, options)?);
+
+        println!("LoadTexture({}) {}x{} {:?} {:?} -- {}ms", path, data.width, data.height, data.format, data.compression, startTime.elapsed().as_millis());
+        self.textures.insert(name.to_owned(), texture.clone());
+
+        Ok(texture)
+    }
+
+    // Registers a texture created at runtime (e.g. an atlas page), so it
+    // shows up in the memory statistics.
+    pub fn InsertTexture(& mut self, name : & str, texture : Rc<Texture>) {
+        self.textures.insert(name.to_owned(), texture);
+    }
+
+    // Releases all assets which are not referenced outside the manager.
+    pub fn CollectGarbage(& mut self) {
+        self.shaders.retain(|_, shader| Rc::strong_count(shader) > 1);
+        self.textures.retain(|_, texture| Rc::strong_count(texture) > 1);
+    }
+
+    // Estimated video memory per texture in bytes, sorted by name.
+    // The memory of shader programs can't be queried in GLES2.
+    pub fn TextureMemory(& self) -> Vec<(String, usize)> {
+        let mut usage : Vec<(String, usize)> = self.textures.iter()
+                                                    .map(|(name, texture)| (name.clone(), texture.GpuMemory()))
+                                                    .collect();
+        usage.sort();
+        usage
+    }
+
+    pub fn TotalTextureMemory(& self) -> usize {
+        self.textures.values().map(|texture| texture.GpuMemory()).sum()
+    }
+}
@@ -0,0 +1,239 @@
+#![allow(non_snake_case)]
+
+use std::collections::HashMap;
+use std::fs;
+use std::rc::Rc;
+use std::time::Instant;
+
+use cache;
+use error::{ Error, Result };
+use texture;
+use texture::{ PixelFormat, Texture, TextureCompression, TextureData, TextureOptions };
+
+// Location of a single image inside the atlas
+#[derive(Clone, Copy, Debug)]
+pub struct AtlasRegion {
+    // index of the page texture the image was placed on
+    pub page : usize,
+    // texture coordinates of the top left and bottom right corner
+    pub uvMin : [f32; 2],
+    pub uvMax : [f32; 2],
+    // size of the image in pixels
+    pub width : u32,
+    pub height : u32,
+}
+
+pub struct TextureAtlas {
+    pub pages : Vec<Rc<Texture>>,
+    regions : HashMap<String, AtlasRegion>,
+}
+
+impl TextureAtlas {
+
+    pub fn Region(& self, name : & str) -> Option<& AtlasRegion> {
+        self.regions.get(name)
+    }
+
+    // the texture the region is located on
+    pub fn Page(& self, region : & AtlasRegion) -> & Rc<Texture> {
+        & self.pages[region.page]
+    }
+}
+
+// Pages packed on the CPU, not uploaded yet. Unlike TextureAtlas it can be
+// sent to another thread, so packing doesn't have to block the GL thread.
+pub struct PackedAtlas {
+    pub pages : Vec<TextureData>,
+    regions : HashMap<String, AtlasRegion>,
+}
+
+impl PackedAtlas {
+
+    // Uploads the pages as RGBA textures. Has to be called on the thread
+    // owning the GL context.
+    pub fn Upload(self, options : & TextureOptions) -> Result<TextureAtlas> {
+
+        let startTime = Instant::now();
+
+        let mut pages = vec![];
+        for data in self.pages.iter() {
+            pages.push(Rc::new(texture::UploadTexture(data, options)?));
+        }
+
+        println!("UploadAtlas({} pages) -- {}ms", pages.len(), startTime.elapsed().as_millis());
+
+        Ok(TextureAtlas {
+            pages: pages,
+            regions: self.regions,
+        })
+    }
+}
+
+// Combines many small images (icons, ...) into a few square page textures.
+// Images are packed on shelves: sorted by height, placed left to right and
+// a new shelf is started once a row is full. Adding the same images in the
+// same order always yields the same layout.
+pub struct AtlasBuilder {
+    pageSize : u32,
+    // empty pixels between two images, avoids bleeding when filtering
+    padding : u32,
+    images : Vec<(String, TextureData)>,
+}
+
+impl AtlasBuilder {
+
+    pub fn new(pageSize : u32, padding : u32) -> AtlasBuilder {
+        AtlasBuilder {
+            pageSize: pageSize,
+            padding: padding,
+            images: vec![],
+        }
+    }
+
+    // The image has to be uncompressed, it's copied into the page pixels.
+    pub fn Add(& mut self, name : & str, data : TextureData) {
+        debug_assert!(data.compression == TextureCompression::None, "compressed images can't be packed into an atlas");
+        self.images.push((name.to_owned(), data));
+    }
+
+    // Adds all PNG images in the directory. The file name without the
+    // extension becomes the name of the region. Uncompressed entries of the
+    // asset cache are used when up to date.
+    pub fn AddDirectory(& mut self, dirPath : & str, options : & TextureOptions) -> Result<()> {
+
+        let entries = match fs::read_dir(dirPath) {
+            Ok(entries) => entries,
+            Err(err) => return Err(Error::Io(dirPath.to_owned(), err))
+        };
+
+        let mut paths = vec![];
+        for entry in entries {
+            match entry {
+                Ok(entry) => paths.push(entry.path()),
+                Err(err) => return Err(Error::Io(dirPath.to_owned(), err))
+            }
+        }
+
+        paths.retain(|path| path.extension().map_or(false, |ext| ext == "png"));
+        paths.sort();
+
+        for path in paths {
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            let sourcePath = path.to_string_lossy().into_owned();
+            let cachePath = cache::CachePath(dirPath, & path.file_name().unwrap().to_string_lossy());
+
+            let data = match cache::ReadTextureCache(& sourcePath, & cachePath, options) {
+                Some(ref data) if data.compression != TextureCompression::None => texture::DecodeTexture(& sourcePath, options)?,
+                Some(data) => data,
+                None => texture::DecodeTexture(& sourcePath, options)?,
+            };
+            self.Add(& name, data);
+        }
+
+        Ok(())
+    }
+
+    // Packs all images and uploads the pages as RGBA textures.
+    pub fn Build(self, options : & TextureOptions) -> Result<TextureAtlas> {
+        self.Pack()?.Upload(options)
+    }
+
+    // Packs all images into RGBA pages without touching GL, so it can run
+    // on a worker thread.
+    pub fn Pack(self) -> Result<PackedAtlas> {
+
+        let startTime = Instant::now();
+
+        let pageSize = self.pageSize;
+        let padding = self.padding;
+
+        // tallest images first, keeps the shelves tight
+        let mut order : Vec<usize> = (0 .. self.images.len()).collect();
+        order.sort_by(|a, b| self.images[*b].1.height.cmp(& self.images[*a].1.height));
+
+        let mut pagePixels : Vec<Vec<u8>> = vec![];
+        let mut regions = HashMap::new();
+
+        let mut cursorX = padding;
+        let mut cursorY = padding;
+        let mut shelfHeight = 0;
+
+        for idx in order {
+            let (ref name, ref data) = self.images[idx];
+
+            if data.width + 2 * padding > pageSize || data.height + 2 * padding > pageSize {
+                return Err(Error::AtlasImageTooLarge(name.clone()));
+            }
+
+            // start a new shelf
+            if cursorX + data.width + padding > pageSize {
+                cursorX = padding;
+                cursorY += shelfHeight + padding;
+                shelfHeight = 0;
+            }
+
+            // start a new page
+            if pagePixels.is_empty() || cursorY + data.height + padding > pageSize {
+                pagePixels.push(vec![0u8; (pageSize * pageSize * 4) as usize]);
+                cursorX = padding;
+                cursorY = padding;
+                shelfHeight = 0;
+            }
+
+            let page = pagePixels.len() - 1;
+            CopyToRGBA(data, & mut pagePixels[page], pageSize, cursorX, cursorY);
+
+            regions.insert(name.clone(), AtlasRegion {
+                page: page,
+                uvMin: [ cursorX as f32 / pageSize as f32, cursorY as f32 / pageSize as f32 ],
+                uvMax: [ (cursorX + data.width) as f32 / pageSize as f32, (cursorY + data.height) as f32 / pageSize as f32 ],
+                width: data.width,
+                height: data.height,
+            });
+
+            cursorX += data.width + padding;
+            if data.height > shelfHeight {
+                shelfHeight = data.height;
+            }
+        }
+
+        let pages : Vec<TextureData> = pagePixels.into_iter().map(|pixels| {
+            TextureData {
+                width: pageSize,
+                height: pageSize,
+                format: PixelFormat::RGBA,
+                compression: TextureCompression::None,
+                pixels: pixels,
+            }
+        }).collect();
+
+        println!("PackAtlas({} images) into {} pages -- {}ms", regions.len(), pages.len(), startTime.elapsed().as_millis());
+
+        Ok(PackedAtlas {
+            pages: pages,
+            regions: regions,
+        })
+    }
+}
+
+// Copies the image into the RGBA page at the given position, converting
+// the pixel format on the way.
+fn CopyToRGBA(data : & TextureData, page : & mut [u8], pageSize : u32, x : u32, y : u32) {
+
+    let bpp = texture::BytesPerPixel(& data.format) as usize;
+
+    for row in 0 .. data.height as usize {
+        for col in 0 .. data.width as usize {
+            let src = & data.pixels[(row * data.width as usize + col) * bpp ..][.. bpp];
+            let rgba = match data.format {
+                PixelFormat::Luminance => [ src[0], src[0], src[0], 255 ],
+                PixelFormat::LuminanceAlpha => [ src[0], src[0], src[0], src[1] ],
+                PixelFormat::RGB => [ src[0], src[1], src[2], 255 ],
+                PixelFormat::RGBA => [ src[0], src[1], src[2], src[3] ],
+            };
+
+            let dst = ((y as usize + row) * pageSize as usize + x as usize + col) * 4;
+            page[dst .. dst + 4].copy_from_slice(& rgba);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+#![allow(non_snake_case)]
+
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::time::Duration;
+
+use duration;
+use error::{ Error, Result };
+
+// Default location of the official 7" display's backlight
+pub const DEFAULT_BACKLIGHT_PATH : & str = "/sys/class/backlight/rpi_backlight";
+
+// Drives the screen brightness through the Linux backlight interface, a
+// directory with 'max_brightness' and a writable 'brightness' file. Any
+// directory with these files works, e.g. a fake one for testing off the Pi.
+// Brightness changes are ramped instead of jumping to the new level.
+pub struct Backlight {
+    root : PathBuf,
+    maxBrightness : u32,
+    // brightness in the range 0..1
+    level : f32,
+    target : f32,
+    // change of the level per second
+    rampSpeed : f32,
+    // last value written to the 'brightness' file
+    written : u32,
+}
+
+impl Backlight {
+
+    pub fn Open(root : & str) -> Result<Backlight> {
+
+        let root = PathBuf::from(root);
+        let maxBrightness = ReadValue(& root.join("max_brightness"))?;
+        let brightness = ReadValue(& root.join("brightness"))?;
+
+        if maxBrightness == 0 {
+            return Err(Error::InvalidBacklight { path: root.to_string_lossy().into_owned(), reason: "max_brightness is 0".to_owned() });
+        }
+
+        let level = (brightness as f32 / maxBrightness as f32).min(1.0);
+
+        Ok(Backlight {
+            root: root,
+            maxBrightness: maxBrightness,
+            level: level,
+            target: level,
+            rampSpeed: 0.0,
+            written: brightness,
+        })
+    }
+
+    pub fn Level(& self) -> f32 {
+        self.level
+    }
+
+    // Starts ramping to the level (0..1), it's reached after rampTime.
+    pub fn SetTarget(& mut self, level : f32, rampTime : Duration) {
+        self.target = level.max(0.0).min(1.0);
+
+        let seconds = duration::Seconds(rampTime);
+        self.rampSpeed = if seconds > 0.0 { (self.target - self.level).abs() / seconds } else { 1.0e6 };
+    }
+
+    // Advances the ramp, only writes to sysfs when the brightness value
+    // actually changes.
+    pub fn Update(& mut self, dt : Duration) -> Result<()> {
+
+        if self.level == self.target {
+            return Ok(());
+        }
+
+        let step = self.rampSpeed * duration::Seconds(dt);
+
+        self.level = if self.level < self.target {
+            (self.level + step).min(self.target)
+        } else {
+            (self.level - step).max(self.target)
+        };
+
+        let brightness = (self.level * self.maxBrightness as f32).round() as u32;
+        if brightness == self.written {
+            return Ok(());
+        }
+
+        let path = self.root.join("brightness");
+        if let Err(err) = fs::write(& path, brightness.to_string()) {
+            return Err(Error::Io(path.to_string_lossy().into_owned(), err));
+        }
+
+        self.written = brightness;
+        Ok(())
+    }
+}
+
+fn ReadValue(path : & Path) -> Result<u32> {
+
+    let pathStr = path.to_string_lossy().into_owned();
+
+    if !path.exists() {
+        return Err(Error::FileNotFound(pathStr));
+    }
+
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) => return Err(Error::Io(pathStr, err))
+    };
+
+    match content.trim().parse::<u32>() {
+        Ok(value) => Ok(value),
+        Err(_) => Err(Error::InvalidBacklight { path: pathStr, reason: format!("'{}' is not a number", content.trim()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+    use std::time::Duration;
+
+    use super::Backlight;
+    use error::Error;
+
+    // A backlight directory in the temp dir, removed again when dropped
+    struct FakeSysfs {
+        root : PathBuf,
+    }
+
+    impl FakeSysfs {
+
+        fn new(name : & str, maxBrightness : & str, brightness : & str) -> FakeSysfs {
+            let root = env::temp_dir().join(format!("ebola-backlight-{}-{}", process::id(), name));
+            fs::create_dir_all(& root).unwrap();
+            fs::write(root.join("max_brightness"), maxBrightness).unwrap();
+            fs::write(root.join("brightness"), brightness).unwrap();
+
+            FakeSysfs {
+                root: root,
+            }
+        }
+
+        fn Path(& self) -> String {
+            self.root.to_string_lossy().into_owned()
+        }
+
+        fn Brightness(& self) -> String {
+            fs::read_to_string(self.root.join("brightness")).unwrap()
+        }
+    }
+
+    impl Drop for FakeSysfs {
+        fn drop(& mut self) {
+            let _ = fs::remove_dir_all(& self.root);
+        }
+    }
+
+    #[test]
+    fn OpenReadsTheCurrentLevel() {
+        let sysfs = FakeSysfs::new("open", "255\n", "51\n");
+        let backlight = Backlight::Open(& sysfs.Path()).unwrap();
+
+        assert!((backlight.Level() - 0.2).abs() < 0.001);
+    }
+
+    #[test]
+    fn OpenRejectsInvalidDirectories() {
+        let sysfs = FakeSysfs::new("invalid", "0", "0");
+        match Backlight::Open(& sysfs.Path()) {
+            Err(Error::InvalidBacklight { .. }) => (),
+            _ => panic!("max_brightness of 0 accepted"),
+        }
+
+        fs::remove_file(sysfs.root.join("brightness")).unwrap();
+        match Backlight::Open(& sysfs.Path()) {
+            Err(Error::FileNotFound(_)) => (),
+            _ => panic!("missing brightness file accepted"),
+        }
+    }
+
+    #[test]
+    fn UpdateRampsToTheTarget() {
+        let sysfs = FakeSysfs::new("ramp", "200", "0");
+        let mut backlight = Backlight::Open(& sysfs.Path()).unwrap();
+
+        backlight.SetTarget(1.0, Duration::from_secs(2));
+
+        backlight.Update(Duration::from_secs(1)).unwrap();
+        assert_eq!(sysfs.Brightness(), "100");
+
+        backlight.Update(Duration::from_secs(5)).unwrap();
+        assert_eq!(sysfs.Brightness(), "200");
+        assert_eq!(backlight.Level(), 1.0);
+    }
+
+    #[test]
+    fn UpdateSkipsUnchangedValues() {
+        let sysfs = FakeSysfs::new("unchanged", "10", "5");
+        let mut backlight = Backlight::Open(& sysfs.Path()).unwrap();
+
+        // the level changes, but still rounds to the written brightness
+        backlight.SetTarget(0.52, Duration::from_secs(0));
+        fs::write(sysfs.root.join("brightness"), "untouched").unwrap();
+        backlight.Update(Duration::from_millis(10)).unwrap();
+
+        assert_eq!(sysfs.Brightness(), "untouched");
+    }
+}
@@ -0,0 +1,414 @@
+#![allow(non_snake_case)]
+
+use std::fs;
+use std::mem;
+use std::path::Path;
+use std::time::{ Instant, UNIX_EPOCH };
+
+use libc;
+use opengles::glesv2 as gl;
+
+use error::{ Error, Result };
+use etc1;
+use renderer;
+use texture;
+use texture::{ PixelFormat, TextureCompression, TextureData, TextureOptions };
+
+// Decoding PNGs at every boot is slow on the Pi. The asset build step
+// (--build-asset-cache) stores every image of the data directory as a blob
+// that can be uploaded as is. Each blob remembers the modification time and
+// size of its source, a cache entry that doesn't match its source anymore is
+// ignored and the source is decoded instead.
+//
+// Layout, little endian:
+//   magic "EBTX", version u32, format u8, compression u8, premultiplied u8,
+//   width u32, height u32, source mtime u64, source size u64, pixels
+//
+// Linked shader programs are cached as well if the driver supports
+// GL_OES_get_program_binary. A program binary is keyed by a hash of the
+// preprocessed sources and the defines, so changing an included file
+// invalidates it too. The driver may still reject a binary (e.g. after a
+// firmware update), then the shader is compiled from source.
+//
+// Layout, little endian:
+//   magic "EBPG", version u32, source hash u64, binary format u32, binary
+
+const MAGIC : &[u8; 4] = b"EBTX";
+const VERSION : u32 = 1;
+const HEADER_SIZE : usize = 4 + 4 + 3 + 4 + 4 + 8 + 8;
+
+const PROGRAM_MAGIC : &[u8; 4] = b"EBPG";
+const PROGRAM_VERSION : u32 = 1;
+const PROGRAM_HEADER_SIZE : usize = 4 + 4 + 8 + 4;
+
+// from GL_OES_get_program_binary
+const GL_PROGRAM_BINARY_LENGTH_OES : gl::GLenum = 0x8741;
+
+const CACHE_DIR : & str = "cache";
+
+// Location of the cache entry for the image at relPath
+pub fn CachePath(dataPath : & str, relPath : & str) -> String {
+    format!("{}/{}/{}.tex", dataPath, CACHE_DIR, relPath)
+}
+
+// Loads the image from the cache if the entry is up to date, else decodes
+// the source. Can be called from any thread.
+pub fn LoadTextureData(sourcePath : & str, cachePath : & str, options : & TextureOptions) -> Result<TextureData> {
+    match ReadTextureCache(sourcePath, cachePath, options) {
+        Some(data) => Ok(data),
+        None => texture::DecodeTexture(sourcePath, options),
+    }
+}
+
+// None if there is no entry, it's unreadable or stale
+pub fn ReadTextureCache(sourcePath : & str, cachePath : & str, options : & TextureOptions) -> Option<TextureData> {
+
+    let blob = fs::read(cachePath).ok()?;
+
+    if blob.len() < HEADER_SIZE || &blob[0 .. 4] != MAGIC || ReadU32(& blob, 4) != VERSION {
+        println!("Ignoring invalid cache entry {}", cachePath);
+        return None;
+    }
+
+    let format = FormatFromByte(blob[8])?;
+    let compression = CompressionFromByte(blob[9])?;
+    let premultiplied = blob[10] != 0;
+    let width = ReadU32(& blob, 11);
+    let height = ReadU32(& blob, 15);
+    let sourceStamp = (ReadU64(& blob, 19), ReadU64(& blob, 27));
+
+    if SourceStamp(sourcePath) != Some(sourceStamp) || premultiplied != options.premultiplyAlpha {
+        println!("Ignoring stale cache entry {}", cachePath);
+        return None;
+    }
+
+    let expectedSize = match compression {
+        TextureCompression::None => (width * height * texture::BytesPerPixel(& format)) as usize,
+        TextureCompression::ETC1 => etc1::CompressedSize(width, height),
+    };
+
+    if blob.len() - HEADER_SIZE != expectedSize {
+        println!("Ignoring truncated cache entry {}", cachePath);
+        return None;
+    }
+
+    Some(TextureData {
+        width: width,
+        height: height,
+        format: format,
+        compression: compression,
+        pixels: blob[HEADER_SIZE ..].to_vec(),
+    })
+}
+
+// Decodes the source image and writes its cache entry. RGB images are ETC1
+// compressed if compress is set, images with alpha are always stored raw.
+pub fn WriteTextureCache(sourcePath : & str, cachePath : & str, options : & TextureOptions, compress : bool) -> Result<()> {
+
+    let mut data = texture::DecodeTexture(sourcePath, options)?;
+
+    if compress && data.format == PixelFormat::RGB {
+        data.pixels = etc1::CompressETC1(data.width, data.height, & data.pixels);
+        data.compression = TextureCompression::ETC1;
+    }
+
+    let (mtime, size) = match SourceStamp(sourcePath) {
+        Some(stamp) => stamp,
+        None => return Err(Error::FileNotFound(sourcePath.to_owned()))
+    };
+
+    let mut blob = Vec::with_capacity(HEADER_SIZE + data.pixels.len());
+    blob.extend_from_slice(MAGIC);
+    WriteU32(& mut blob, VERSION);
+    blob.push(FormatToByte(data.format));
+    blob.push(CompressionToByte(data.compression));
+    blob.push(options.premultiplyAlpha as u8);
+    WriteU32(& mut blob, data.width);
+    WriteU32(& mut blob, data.height);
+    WriteU64(& mut blob, mtime);
+    WriteU64(& mut blob, size);
+    blob.extend_from_slice(& data.pixels);
+
+    WriteCacheFile(cachePath, & blob)
+}
+
+////////////////////////////////////
+// Program binaries
+
+// The bindings don't expose the entry points of GL_OES_get_program_binary,
+// they are looked up through EGL. libEGL is linked by the egl crate.
+extern "C" {
+    fn eglGetProcAddress(procname : *const libc::c_char) -> *const libc::c_void;
+}
+
+type GetProgramBinaryFn = extern "C" fn(gl::GLuint, gl::GLsizei, *mut gl::GLsizei, *mut gl::GLenum, *mut libc::c_void);
+type ProgramBinaryFn = extern "C" fn(gl::GLuint, gl::GLenum, *const libc::c_void, gl::GLint);
+
+pub struct ProgramBinaryExt {
+    getProgramBinary : GetProgramBinaryFn,
+    programBinary : ProgramBinaryFn,
+}
+
+impl ProgramBinaryExt {
+
+    // None if the driver doesn't support program binaries. Needs a current
+    // GL context.
+    pub fn Load() -> Option<ProgramBinaryExt> {
+
+        if !HasExtension("GL_OES_get_program_binary") {
+            return None;
+        }
+
+        let getProgramBinary = unsafe { eglGetProcAddress(b"glGetProgramBinaryOES\0".as_ptr() as *const libc::c_char) };
+        let programBinary = unsafe { eglGetProcAddress(b"glProgramBinaryOES\0".as_ptr() as *const libc::c_char) };
+
+        if getProgramBinary.is_null() || programBinary.is_null() {
+            return None;
+        }
+
+        Some(ProgramBinaryExt {
+            getProgramBinary: unsafe { mem::transmute::<*const libc::c_void, GetProgramBinaryFn>(getProgramBinary) },
+            programBinary: unsafe { mem::transmute::<*const libc::c_void, ProgramBinaryFn>(programBinary) },
+        })
+    }
+
+    // Binary format and binary of a linked program
+    pub fn GetBinary(& self, program : gl::GLuint) -> Option<(gl::GLenum, Vec<u8>)> {
+
+        let length = gl::get_programiv(program, GL_PROGRAM_BINARY_LENGTH_OES);
+        if length <= 0 {
+            return None;
+        }
+
+        let mut binary = vec![0u8; length as usize];
+        let mut written : gl::GLsizei = 0;
+        let mut format : gl::GLenum = 0;
+
+        (self.getProgramBinary)(program, length, & mut written, & mut format, binary.as_mut_ptr() as *mut libc::c_void);
+
+        if written <= 0 {
+            return None;
+        }
+
+        binary.truncate(written as usize);
+        Some((format, binary))
+    }
+
+    // Loads the binary into the program, false if the driver rejected it
+    pub fn LoadBinary(& self, program : gl::GLuint, format : gl::GLenum, binary : &[u8]) -> bool {
+        (self.programBinary)(program, format, binary.as_ptr() as *const libc::c_void, binary.len() as gl::GLint);
+        gl::get_programiv(program, gl::GL_LINK_STATUS) != gl::GL_FALSE as i32
+    }
+}
+
+// Location of the program binary of a shader variant, name is relative to
+// the data directory. The order of the defines doesn't matter.
+pub fn ProgramCachePath(dataPath : & str, name : & str, defines : &[& str]) -> String {
+    let mut sortedDefines = defines.to_vec();
+    sortedDefines.sort();
+
+    let key = sortedDefines.iter().fold(name.to_owned(), |key, define| key + "#" + define);
+    format!("{}/{}/{}.prog", dataPath, CACHE_DIR, key)
+}
+
+// FNV-1a over the preprocessed sources and the defines
+pub fn ShaderSourceHash(vertCode : & str, fragCode : & str, defines : &[& str]) -> u64 {
+
+    let mut hash : u64 = 0xcbf29ce484222325;
+    let sources = [vertCode, fragCode];
+
+    for part in sources.iter().chain(defines.iter()) {
+        // the separator keeps "ab" + "c" apart from "a" + "bc"
+        for & byte in part.as_bytes().iter().chain([0u8].iter()) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    hash
+}
+
+// Binary format and binary, None if there is no entry, it's unreadable or
+// was built from other sources
+pub fn ReadProgramCache(cachePath : & str, sourceHash : u64) -> Option<(gl::GLenum, Vec<u8>)> {
+
+    let blob = fs::read(cachePath).ok()?;
+
+    if blob.len() < PROGRAM_HEADER_SIZE || &blob[0 .. 4] != PROGRAM_MAGIC || ReadU32(& blob, 4) != PROGRAM_VERSION {
+        println!("Ignoring invalid cache entry {}", cachePath);
+        return None;
+    }
+
+    if ReadU64(& blob, 8) != sourceHash {
+        println!("Ignoring stale cache entry {}", cachePath);
+        return None;
+    }
+
+    Some((ReadU32(& blob, 16), blob[PROGRAM_HEADER_SIZE ..].to_vec()))
+}
+
+pub fn WriteProgramCache(cachePath : & str, sourceHash : u64, format : gl::GLenum, binary : &[u8]) -> Result<()> {
+
+    let mut blob = Vec::with_capacity(PROGRAM_HEADER_SIZE + binary.len());
+    blob.extend_from_slice(PROGRAM_MAGIC);
+    WriteU32(& mut blob, PROGRAM_VERSION);
+    WriteU64(& mut blob, sourceHash);
+    WriteU32(& mut blob, format);
+    blob.extend_from_slice(binary);
+
+    WriteCacheFile(cachePath, & blob)
+}
+
+// Links every shader variant of the data directory (see
+// renderer::ListShaderVariants) and writes its program binary.
+fn BuildProgramCache(dataPath : & str, ext : & ProgramBinaryExt) -> Result<Vec<(String, Result<()>)>> {
+
+    let variants = renderer::ListShaderVariants(dataPath)?;
+
+    Ok(variants.into_iter().map(|variant| {
+        let startTime = Instant::now();
+        let sourcePath = format!("{}/{}", dataPath, variant.name);
+        let defines : Vec<& str> = variant.defines.iter().map(|define| define.as_str()).collect();
+        let cachePath = ProgramCachePath(dataPath, & variant.name, & defines);
+
+        let result = renderer::LoadShaderStageVariant(& sourcePath, & defines).and_then(|stage| {
+            match stage.ProgramBinary(ext) {
+                Some((format, binary)) => WriteProgramCache(& cachePath, stage.SourceHash(), format, & binary),
+                None => Err(Error::ProgramBinaryUnavailable(sourcePath.clone())),
+            }
+        });
+
+        println!("CacheProgram({} {:?}) -- {}ms", sourcePath, defines, startTime.elapsed().as_millis());
+
+        (format!("{} {:?}", sourcePath, defines), result)
+    }).collect())
+}
+
+////////////////////////////////////
+// Asset build step
+
+// Writes the cache entries for all PNG images and shader variants in the
+// data directory and returns the result per asset. Needs a current GL
+// context to find out whether the GPU takes ETC1 textures and program
+// binaries.
+pub fn BuildAssetCache(dataPath : & str, compress : bool) -> Result<Vec<(String, Result<()>)>> {
+
+    let compress = compress && HasExtension("GL_OES_compressed_ETC1_RGB8_texture");
+
+    let entries = match fs::read_dir(dataPath) {
+        Ok(entries) => entries,
+        Err(err) => return Err(Error::Io(dataPath.to_owned(), err))
+    };
+
+    let mut names = vec![];
+    for entry in entries {
+        match entry {
+            Ok(entry) => {
+                let path = entry.path();
+                if path.extension().map_or(false, |ext| ext == "png") {
+                    names.push(entry.file_name().to_string_lossy().into_owned());
+                }
+            },
+            Err(err) => return Err(Error::Io(dataPath.to_owned(), err))
+        }
+    }
+    names.sort();
+
+    let options = TextureOptions::default();
+
+    let mut results : Vec<(String, Result<()>)> = names.into_iter().map(|name| {
+        let startTime = Instant::now();
+        let sourcePath = format!("{}/{}", dataPath, name);
+        let result = WriteTextureCache(& sourcePath, & CachePath(dataPath, & name), & options, compress);
+
+        println!("CacheTexture({}) -- {}ms", sourcePath, startTime.elapsed().as_millis());
+
+        (sourcePath, result)
+    }).collect();
+
+    match ProgramBinaryExt::Load() {
+        Some(ext) => results.extend(BuildProgramCache(dataPath, & ext)?),
+        None => println!("GL_OES_get_program_binary is not supported, shaders are compiled from source"),
+    }
+
+    Ok(results)
+}
+
+fn WriteCacheFile(cachePath : & str, blob : &[u8]) -> Result<()> {
+
+    if let Some(dir) = Path::new(cachePath).parent() {
+        if let Err(err) = fs::create_dir_all(dir) {
+            return Err(Error::Io(dir.to_string_lossy().into_owned(), err));
+        }
+    }
+
+    match fs::write(cachePath, blob) {
+        Ok(()) => Ok(()),
+        Err(err) => Err(Error::Io(cachePath.to_owned(), err))
+    }
+}
+
+fn HasExtension(name : & str) -> bool {
+    gl::get_string(gl::GL_EXTENSIONS).split_whitespace().any(|ext| ext == name)
+}
+
+// modification time in seconds and size of the file
+fn SourceStamp(path : & str) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some((mtime, metadata.len()))
+}
+
+fn FormatToByte(format : PixelFormat) -> u8 {
+    match format {
+        PixelFormat::Luminance => 0,
+        PixelFormat::LuminanceAlpha => 1,
+        PixelFormat::RGB => 2,
+        PixelFormat::RGBA => 3,
+    }
+}
+
+fn FormatFromByte(value : u8) -> Option<PixelFormat> {
+    match value {
+        0 => Some(PixelFormat::Luminance),
+        1 => Some(PixelFormat::LuminanceAlpha),
+        2 => Some(PixelFormat::RGB),
+        3 => Some(PixelFormat::RGBA),
+        _ => None,
+    }
+}
+
+fn CompressionToByte(compression : TextureCompression) -> u8 {
+    match compression {
+        TextureCompression::None => 0,
+        TextureCompression::ETC1 => 1,
+    }
+}
+
+fn CompressionFromByte(value : u8) -> Option<TextureCompression> {
+    match value {
+        0 => Some(TextureCompression::None),
+        1 => Some(TextureCompression::ETC1),
+        _ => None,
+    }
+}
+
+fn WriteU32(blob : & mut Vec<u8>, value : u32) {
+    for i in 0 .. 4 {
+        blob.push((value >> (i * 8)) as u8);
+    }
+}
+
+fn WriteU64(blob : & mut Vec<u8>, value : u64) {
+    for i in 0 .. 8 {
+        blob.push((value >> (i * 8)) as u8);
+    }
+}
+
+fn ReadU32(blob : &[u8], offset : usize) -> u32 {
+    (0 .. 4).fold(0, |value, i| value | (blob[offset + i] as u32) << (i * 8))
+}
+
+fn ReadU64(blob : &[u8], offset : usize) -> u64 {
+    (0 .. 8).fold(0, |value, i| value | (blob[offset + i] as u64) << (i * 8))
+}
@@ -0,0 +1,320 @@
+#![allow(non_snake_case)]
+
+use std::collections::VecDeque;
+use std::mem;
+use std::rc::Rc;
+use std::time::Duration;
+
+use duration;
+use error::Result;
+use renderer::{ BlendMode,
+                GPUBufferTarget,
+                GPURingBuffer,
+                PrimitivesType,
+                RenderCommand,
+                RenderPass,
+                RenderState,
+                ShaderStage };
+use shapes;
+use shapes::{ LineJoin, ShapeBuilder, ShapeVertex };
+use theme::Color;
+
+// shortest interval between two samples, a zero window would never stop
+// sampling
+const MIN_SAMPLE_INTERVAL : f32 = 0.001;
+
+// Rolling window of a signal, sampled at a fixed interval.
+pub struct TimeSeries {
+    samples : VecDeque<f32>,
+    capacity : usize,
+    // seconds between two samples
+    interval : f32,
+    // time since the last sample
+    elapsed : f32,
+}
+
+impl TimeSeries {
+
+    // Keeps numSamples samples spread over the window
+    pub fn new(window : Duration, numSamples : usize) -> TimeSeries {
+        let numSamples = numSamples.max(2);
+
+        TimeSeries {
+            samples: VecDeque::with_capacity(numSamples),
+            capacity: numSamples,
+            interval: (duration::Seconds(window) / numSamples as f32).max(MIN_SAMPLE_INTERVAL),
+            elapsed: 0.0,
+        }
+    }
+
+    // Advances the time and takes the value as sample once the interval
+    // passed. After a long frame the value is repeated for the missed
+    // samples. Returns whether a sample was taken.
+    pub fn Advance(& mut self, dt : Duration, value : f32) -> bool {
+
+        // a stall longer than the window doesn't need more than one refill
+        self.elapsed = (self.elapsed + duration::Seconds(dt)).min(self.interval * self.capacity as f32);
+
+        let mut sampled = false;
+        while self.elapsed >= self.interval {
+            self.elapsed -= self.interval;
+            if self.samples.len() == self.capacity {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(value);
+            sampled = true;
+        }
+
+        sampled
+    }
+
+    pub fn Clear(& mut self) {
+        self.samples.clear();
+        self.elapsed = 0.0;
+    }
+
+    // oldest first
+    pub fn Samples(& self) -> & VecDeque<f32> {
+        & self.samples
+    }
+
+    pub fn Capacity(& self) -> usize {
+        self.capacity
+    }
+
+    // index and value of the smallest and largest sample
+    pub fn MinMax(& self) -> Option<((usize, f32), (usize, f32))> {
+        let mut iter = self.samples.iter().cloned().enumerate();
+        let first = iter.next()?;
+
+        Some(iter.fold((first, first), |(min, max), sample| {
+            (if sample.1 < min.1 { sample } else { min },
+             if sample.1 > max.1 { sample } else { max })
+        }))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ChartStyle {
+    Line,
+    // line with the area down to the zero line filled
+    Area,
+}
+
+#[derive(Clone, Copy)]
+pub struct ChartColors {
+    pub line : Color,
+    pub fill : Color,
+    pub axis : Color,
+    pub marker : Color,
+}
+
+// frames of geometry the ring buffer can hold before it wraps
+const RING_FRAMES : usize = 3;
+// generous bound of the vertices per sample: line quads with fringes, a bevel
+// join and the area below
+const VERTICES_PER_SAMPLE : usize = 48;
+// axes and markers
+const EXTRA_VERTICES : usize = 2048;
+
+// Plots the recent history of a signal. The geometry is regenerated when a
+// sample was taken and streamed through a ring buffer, so the chart can
+// change every frame without stalling on buffers the GPU still reads.
+// The value axis has no labels, the chart shows the shape of the signal and
+// marks the smallest and largest value of the window.
+pub struct Chart {
+    series : TimeSeries,
+    // x, y, width, height in pixels
+    rect : [f32; 4],
+    style : ChartStyle,
+    // None scales to the samples
+    range : Option<(f32, f32)>,
+    colors : ChartColors,
+    shader : Rc<ShaderStage>,
+    ring : GPURingBuffer,
+    // geometry is out of date
+    dirty : bool,
+}
+
+impl Chart {
+
+    // The shader needs the attributes of shapes::ShapeLayout, e.g. the
+    // default shader with VERTEX_COLOR and EDGE_AA.
+    pub fn new(shader : & Rc<ShaderStage>, rect : [f32; 4], window : Duration, numSamples : usize) -> Chart {
+
+        let numVertices = numSamples * VERTICES_PER_SAMPLE + EXTRA_VERTICES;
+
+        Chart {
+            series: TimeSeries::new(window, numSamples),
+            rect: rect,
+            style: ChartStyle::Line,
+            range: None,
+            colors: ChartColors {
+                line: [1.0, 1.0, 1.0, 1.0],
+                fill: [1.0, 1.0, 1.0, 0.3],
+                axis: [0.5, 0.5, 0.5, 1.0],
+                marker: [1.0, 0.0, 0.0, 1.0],
+            },
+            shader: shader.clone(),
+            ring: GPURingBuffer::new(numVertices * mem::size_of::<ShapeVertex>() * RING_FRAMES, GPUBufferTarget::Array),
+            dirty: true,
+        }
+    }
+
+    pub fn WithStyle(mut self, style : ChartStyle) -> Chart {
+        self.style = style;
+        self
+    }
+
+    // Fixed value range instead of scaling to the samples
+    pub fn WithRange(mut self, min : f32, max : f32) -> Chart {
+        self.range = Some((min, max));
+        self
+    }
+
+    pub fn WithColors(mut self, colors : ChartColors) -> Chart {
+        self.colors = colors;
+        self
+    }
+
+    pub fn SetColors(& mut self, colors : ChartColors) {
+        self.colors = colors;
+        self.dirty = true;
+    }
+
+    // Empty pass, filled by Render
+    pub fn Pass(& self, name : & str, order : i32) -> RenderPass {
+        RenderPass::new(name, self.shader.clone(), order)
+    }
+
+    // Feeds the current value, call every frame. Returns whether the chart
+    // changed and has to be redrawn.
+    pub fn AddSample(& mut self, dt : Duration, value : f32) -> bool {
+        if self.series.Advance(dt, value) {
+            self.dirty = true;
+        }
+        self.dirty
+    }
+
+    pub fn Series(& self) -> & TimeSeries {
+        & self.series
+    }
+
+    // Regenerates the commands of the pass if the chart changed.
+    pub fn Render(& mut self, pass : & mut RenderPass) -> Result<()> {
+
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let (min, max) = self.ValueRange();
+        let points = self.SamplePoints(min, max);
+        let zeroY = self.ValueToY(0.0_f32.max(min).min(max), min, max);
+
+        // the fill has no fringes, they would show as seams between the
+        // segments
+        let mut fill = ShapeBuilder::new().WithFeather(0.0);
+        let mut lines = ShapeBuilder::new();
+
+        if self.style == ChartStyle::Area {
+            FillToBaseline(& mut fill, & points, zeroY, self.colors.fill);
+        }
+
+        self.Axes(& mut lines, zeroY);
+        lines.Polyline(& points, 2.0, LineJoin::Miter, false, self.colors.line);
+        self.Markers(& mut lines, & points);
+
+        // one push, a second one could orphan the storage of the first
+        let mut vertices = fill.Vertices().to_vec();
+        vertices.extend_from_slice(lines.Vertices());
+
+        let offset = self.ring.Push(& vertices)?;
+
+        // bound once at the start of the ring, each push only moves the
+        // base offset of the command
+        if pass.commands.is_empty() {
+            let attribs = self.shader.BindVertexLayout(& shapes::ShapeLayout(), self.ring.Buffer())?;
+            pass.commands.push(RenderCommand::new(attribs, vec![], PrimitivesType::Triangles, 0)
+                                .WithRenderState(RenderState::Blended(BlendMode::Alpha)));
+        }
+
+        let command = & mut pass.commands[0];
+        command.SetBaseOffset(offset);
+        command.SetNumVertices(vertices.len() as u32);
+        self.dirty = false;
+
+        Ok(())
+    }
+
+    fn ValueRange(& self) -> (f32, f32) {
+        if let Some(range) = self.range {
+            return range;
+        }
+
+        // the zero line stays visible, so regen and drive share one scale
+        let (min, max) = match self.series.MinMax() {
+            Some(((_, min), (_, max))) => (min.min(0.0), max.max(0.0)),
+            None => (0.0, 1.0),
+        };
+
+        let padding = ((max - min) * 0.1).max(0.001);
+        (min - padding, max + padding)
+    }
+
+    fn ValueToY(& self, value : f32, min : f32, max : f32) -> f32 {
+        let bottom = self.rect[1] + self.rect[3];
+        let t = ((value - min) / (max - min)).max(0.0).min(1.0);
+        bottom - t * self.rect[3]
+    }
+
+    // the newest sample is at the right edge
+    fn SamplePoints(& self, min : f32, max : f32) -> Vec<shapes::Point> {
+        let samples = self.series.Samples();
+        let step = self.rect[2] / (self.series.Capacity() - 1) as f32;
+        let right = self.rect[0] + self.rect[2];
+        let newest = samples.len() as f32 - 1.0;
+
+        samples.iter().enumerate().map(|(i, & value)| {
+            [ right - (newest - i as f32) * step, self.ValueToY(value, min, max) ]
+        }).collect()
+    }
+
+    fn Axes(& self, builder : & mut ShapeBuilder, zeroY : f32) {
+        let (x, y, width, height) = (self.rect[0], self.rect[1], self.rect[2], self.rect[3]);
+
+        builder.Polyline(&[ [x, y], [x, y + height] ], 1.0, LineJoin::Miter, false, self.colors.axis);
+        builder.Polyline(&[ [x, zeroY], [x + width, zeroY] ], 1.0, LineJoin::Miter, false, self.colors.axis);
+    }
+
+    fn Markers(& self, builder : & mut ShapeBuilder, points : &[shapes::Point]) {
+        let ((minIdx, _), (maxIdx, _)) = match self.series.MinMax() {
+            Some(minMax) => minMax,
+            None => return,
+        };
+
+        for & idx in [ minIdx, maxIdx ].iter() {
+            let p = points[idx];
+            builder.Circle(p, 4.0, self.colors.marker);
+            // line towards the value axis, so the level can be compared
+            builder.Polyline(&[ [self.rect[0], p[1]], [p[0], p[1]] ], 1.0, LineJoin::Miter, false, self.colors.marker);
+        }
+    }
+}
+
+// Trapezoids between the line and the zero line. Segments crossing the zero
+// line are split there, else they would cross themselves.
+fn FillToBaseline(builder : & mut ShapeBuilder, points : &[shapes::Point], baseline : f32, color : Color) {
+
+    for segment in points.windows(2) {
+        let (a, b) = (segment[0], segment[1]);
+
+        if (a[1] - baseline) * (b[1] - baseline) < 0.0 {
+            let t = (baseline - a[1]) / (b[1] - a[1]);
+            let crossing = [ a[0] + (b[0] - a[0]) * t, baseline ];
+            builder.ConvexPolygon(&[ a, crossing, [a[0], baseline] ], color);
+            builder.ConvexPolygon(&[ crossing, b, [b[0], baseline] ], color);
+        } else {
+            builder.ConvexPolygon(&[ a, b, [b[0], baseline], [a[0], baseline] ], color);
+        }
+    }
+}
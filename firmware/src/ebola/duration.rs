@@ -0,0 +1,13 @@
+#![allow(non_snake_case)]
+
+use std::time::Duration;
+
+// Conversions for the timing math, which is done in f32 throughout.
+
+pub fn Seconds(duration : Duration) -> f32 {
+    duration.as_secs() as f32 + duration.subsec_micros() as f32 / 1_000_000.0
+}
+
+pub fn Millis(duration : Duration) -> f32 {
+    duration.as_secs() as f32 * 1000.0 + duration.subsec_micros() as f32 / 1000.0
+}
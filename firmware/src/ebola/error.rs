@@ -0,0 +1,117 @@
+#![allow(non_snake_case)]
+
+extern crate image;
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+use texture;
+
+#[derive(Debug)]
+pub enum Error {
+    // creating the dispmanx window failed
+    Window(&'static str),
+    // a step of the EGL setup failed
+    EGL(&'static str),
+    // the thread of a layer panicked before its setup was complete
+    LayerThreadPanicked,
+    // a required file does not exist
+    FileNotFound(String),
+    // a file exists, but could not be read
+    Io(String, io::Error),
+    // a shader file includes itself, directly or through other files
+    ShaderIncludeCycle(String),
+    // shader failed to compile, contains the info log
+    ShaderCompile { path : String, log : String },
+    // program failed to link, contains the info log
+    ShaderLink { path : String, log : String },
+    // the driver returned no binary for a linked program
+    ProgramBinaryUnavailable(String),
+    // image could not be decoded
+    ImageDecode(String, image::ImageError),
+    // the shader has no active attribute with this name
+    AttributeNotFound(String),
+    // the shader has no active uniform with this name
+    UniformNotFound(String),
+    // the uniform exists, but has a different type (e.g. not a sampler)
+    UniformTypeMismatch(String),
+    // the number of components doesn't match the uniform (1 to 4)
+    InvalidUniformSize(String, usize),
+    // a command samples more textures than there are texture units
+    TooManyTextures(usize),
+    // image does not fit on an atlas page
+    AtlasImageTooLarge(String),
+    // a vertex layout file is malformed or doesn't match the vertex data
+    InvalidLayout { path : String, reason : String },
+    // data written past the end of a GPUBuffer
+    BufferOverflow { requested : usize, capacity : usize },
+    // the sysfs backlight directory is missing files or has unexpected content
+    InvalidBacklight { path : String, reason : String },
+    // a line of a palette file could not be parsed
+    InvalidPalette { path : String, line : usize },
+    PaletteNotFound(String),
+    // no page with this name was added to the PageManager
+    PageNotFound(String),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(& self, f : & mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Window(ref msg) => write!(f, "Failed to create render window: {}", msg),
+            Error::EGL(ref msg) => write!(f, "EGL error: {}", msg),
+            Error::LayerThreadPanicked => write!(f, "Layer thread panicked during setup"),
+            Error::FileNotFound(ref path) => write!(f, "File not found: '{}'", path),
+            Error::Io(ref path, ref err) => write!(f, "Failed to read '{}': {}", path, err),
+            Error::ShaderIncludeCycle(ref path) => write!(f, "Shader '{}' includes itself", path),
+            Error::ShaderCompile { ref path, ref log } => write!(f, "Failed to compile shader '{}':\n{}", path, log),
+            Error::ShaderLink { ref path, ref log } => write!(f, "Failed to link shader '{}':\n{}", path, log),
+            Error::ProgramBinaryUnavailable(ref path) => write!(f, "No program binary available for shader '{}'", path),
+            Error::ImageDecode(ref path, ref err) => write!(f, "Failed to decode image '{}': {}", path, err),
+            Error::AttributeNotFound(ref name) => write!(f, "Failed to bind attribute '{}'", name),
+            Error::UniformNotFound(ref name) => write!(f, "Failed to bind uniform '{}'", name),
+            Error::UniformTypeMismatch(ref name) => write!(f, "Uniform '{}' has a different type", name),
+            Error::InvalidUniformSize(ref name, size) => write!(f, "Invalid number of components ({}) for uniform '{}'", size, name),
+            Error::TooManyTextures(count) => write!(f, "{} textures bound, but only {} texture units are available", count, texture::MAX_TEXTURE_UNITS),
+            Error::AtlasImageTooLarge(ref name) => write!(f, "Image '{}' is too large for the atlas page", name),
+            Error::InvalidLayout { ref path, ref reason } => write!(f, "Invalid vertex layout '{}': {}", path, reason),
+            Error::BufferOverflow { requested, capacity } => write!(f, "Buffer overflow: {} bytes requested, {} bytes available", requested, capacity),
+            Error::InvalidBacklight { ref path, ref reason } => write!(f, "Invalid backlight '{}': {}", path, reason),
+            Error::InvalidPalette { ref path, line } => write!(f, "Invalid palette '{}' in line {}, 'name = #RRGGBB[AA]' expected", path, line),
+            Error::PaletteNotFound(ref name) => write!(f, "Palette '{}' not found", name),
+            Error::PageNotFound(ref name) => write!(f, "Page '{}' not found", name),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(& self) -> & str {
+        match *self {
+            Error::Window(_) => "window creation failed",
+            Error::EGL(_) => "EGL error",
+            Error::LayerThreadPanicked => "layer thread panicked",
+            Error::FileNotFound(_) => "file not found",
+            Error::Io(..) => "io error",
+            Error::ShaderIncludeCycle(_) => "shader include cycle",
+            Error::ShaderCompile { .. } => "shader compilation failed",
+            Error::ShaderLink { .. } => "shader linking failed",
+            Error::ProgramBinaryUnavailable(_) => "program binary unavailable",
+            Error::ImageDecode(..) => "image decoding failed",
+            Error::AttributeNotFound(_) => "attribute not found",
+            Error::UniformNotFound(_) => "uniform not found",
+            Error::UniformTypeMismatch(_) => "uniform type mismatch",
+            Error::InvalidUniformSize(..) => "invalid uniform size",
+            Error::TooManyTextures(_) => "too many textures",
+            Error::AtlasImageTooLarge(_) => "image too large for atlas",
+            Error::InvalidLayout { .. } => "invalid vertex layout",
+            Error::BufferOverflow { .. } => "buffer overflow",
+            Error::InvalidBacklight { .. } => "invalid backlight",
+            Error::InvalidPalette { .. } => "invalid palette",
+            Error::PaletteNotFound(_) => "palette not found",
+            Error::PageNotFound(_) => "page not found",
+        }
+    }
+}
@@ -0,0 +1,168 @@
+#![allow(non_snake_case)]
+
+// Encoder for ETC1, the compressed texture format of the VideoCore IV
+// (GL_OES_compressed_ETC1_RGB8_texture). Every 4x4 block of RGB pixels is
+// stored in 8 bytes, a sixth of the uncompressed size. Only the individual
+// mode is used: the block is split into two halves, each gets a 4 bit base
+// color and a modifier table, every pixel picks one of the table's offsets.
+
+// GL_ETC1_RGB8_OES, not part of the core GLES2 headers
+pub const GL_ETC1_RGB8_OES : u32 = 0x8D64;
+
+const MODIFIER_TABLES : [[i32; 4]; 8] = [
+    [  2,   8,   -2,   -8 ],
+    [  5,  17,   -5,  -17 ],
+    [  9,  29,   -9,  -29 ],
+    [ 13,  42,  -13,  -42 ],
+    [ 18,  60,  -18,  -60 ],
+    [ 24,  80,  -24,  -80 ],
+    [ 33, 106,  -33, -106 ],
+    [ 47, 183,  -47, -183 ],
+];
+
+// Size of the compressed image in bytes, partial blocks are padded
+pub fn CompressedSize(width : u32, height : u32) -> usize {
+    (((width + 3) / 4) * ((height + 3) / 4) * 8) as usize
+}
+
+// Compresses tightly packed RGB pixels
+pub fn CompressETC1(width : u32, height : u32, rgb : & [u8]) -> Vec<u8> {
+
+    let mut compressed = Vec::with_capacity(CompressedSize(width, height));
+
+    for blockY in 0 .. (height + 3) / 4 {
+        for blockX in 0 .. (width + 3) / 4 {
+
+            // pixels outside the image repeat the closest edge pixel
+            let mut block = [[0i32; 3]; 16];
+            for y in 0 .. 4 {
+                for x in 0 .. 4 {
+                    let px = (blockX * 4 + x).min(width - 1);
+                    let py = (blockY * 4 + y).min(height - 1);
+                    let idx = ((py * width + px) * 3) as usize;
+                    block[(y * 4 + x) as usize] = [ rgb[idx] as i32, rgb[idx + 1] as i32, rgb[idx + 2] as i32 ];
+                }
+            }
+
+            let bits = EncodeBlock(& block);
+            for shift in (0 .. 8).rev() {
+                compressed.push((bits >> (shift * 8)) as u8);
+            }
+        }
+    }
+
+    compressed
+}
+
+struct SubBlock {
+    // 4 bit base color
+    base : [i32; 3],
+    table : usize,
+    // squared error over the sub block's pixels
+    error : i64,
+}
+
+fn EncodeBlock(block : & [[i32; 3]; 16]) -> u64 {
+
+    let mut best : Option<(i64, u64)> = None;
+
+    for flip in 0 .. 2 {
+        let mut bits = 0u64;
+        let mut error = 0;
+        let mut indices = [0usize; 16];
+
+        for half in 0 .. 2 {
+            // flip 0: left and right 2x4, flip 1: top and bottom 4x2
+            let pixels : Vec<usize> = (0 .. 16).filter(|&i| {
+                let (x, y) = (i % 4, i / 4);
+                let inSecond = if flip == 0 { x >= 2 } else { y >= 2 };
+                inSecond == (half == 1)
+            }).collect();
+
+            let subBlock = EncodeSubBlock(block, & pixels, & mut indices);
+            error += subBlock.error;
+
+            let shift = if half == 0 { 4 } else { 0 };
+            bits |= (subBlock.base[0] as u64) << (56 + shift);
+            bits |= (subBlock.base[1] as u64) << (48 + shift);
+            bits |= (subBlock.base[2] as u64) << (40 + shift);
+            bits |= (subBlock.table as u64) << (if half == 0 { 37 } else { 34 });
+        }
+
+        // individual mode, diff bit stays 0
+        bits |= (flip as u64) << 32;
+
+        // the pixel indices are stored column by column, msb and lsb apart
+        for (i, index) in indices.iter().enumerate() {
+            let (x, y) = (i % 4, i / 4);
+            let bit = x * 4 + y;
+            bits |= ((*index as u64 >> 1) & 1) << (16 + bit);
+            bits |= (*index as u64 & 1) << bit;
+        }
+
+        if best.map_or(true, |(bestError, _)| error < bestError) {
+            best = Some((error, bits));
+        }
+    }
+
+    best.unwrap().1
+}
+
+fn EncodeSubBlock(block : & [[i32; 3]; 16], pixels : & [usize], indices : & mut [usize; 16]) -> SubBlock {
+
+    let mut sum = [0i32; 3];
+    for &i in pixels.iter() {
+        for c in 0 .. 3 {
+            sum[c] += block[i][c];
+        }
+    }
+
+    let count = pixels.len() as i32;
+    let mut base = [0i32; 3];
+    let mut expanded = [0i32; 3];
+    for c in 0 .. 3 {
+        let average = (sum[c] + count / 2) / count;
+        base[c] = (average * 15 + 127) / 255;
+        expanded[c] = (base[c] << 4) | base[c];
+    }
+
+    let mut best = SubBlock { base: base, table: 0, error: i64::max_value() };
+    let mut bestIndices = [0usize; 16];
+
+    for (table, modifiers) in MODIFIER_TABLES.iter().enumerate() {
+        let mut error = 0i64;
+        let mut tableIndices = [0usize; 16];
+
+        for &i in pixels.iter() {
+            let mut pixelBest = (i64::max_value(), 0);
+
+            for (index, modifier) in modifiers.iter().enumerate() {
+                let mut pixelError = 0i64;
+                for c in 0 .. 3 {
+                    let value = (expanded[c] + modifier).max(0).min(255);
+                    let diff = (value - block[i][c]) as i64;
+                    pixelError += diff * diff;
+                }
+
+                if pixelError < pixelBest.0 {
+                    pixelBest = (pixelError, index);
+                }
+            }
+
+            error += pixelBest.0;
+            tableIndices[i] = pixelBest.1;
+        }
+
+        if error < best.error {
+            best.table = table;
+            best.error = error;
+            bestIndices = tableIndices;
+        }
+    }
+
+    for &i in pixels.iter() {
+        indices[i] = bestIndices[i];
+    }
+
+    best
+}
@@ -0,0 +1,251 @@
+#![allow(non_snake_case)]
+
+use std::fs::{ File, OpenOptions };
+use std::io;
+use std::io::Read;
+use std::mem;
+use std::os::unix::fs::OpenOptionsExt;
+use std::time::{ Duration, Instant };
+
+use libc;
+
+use error::{ Error, Result };
+
+// Default event device of the official 7" display's touch controller
+pub const DEFAULT_TOUCH_DEVICE : & str = "/dev/input/event0";
+
+// evdev event types and codes, see linux/input-event-codes.h
+const EV_SYN : u16 = 0x00;
+const EV_KEY : u16 = 0x01;
+const EV_ABS : u16 = 0x03;
+const SYN_REPORT : u16 = 0x00;
+const BTN_TOUCH : u16 = 0x14a;
+const ABS_X : u16 = 0x00;
+const ABS_Y : u16 = 0x01;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TouchPhase {
+    Down,
+    Move,
+    Up,
+}
+
+// position in screen pixels
+#[derive(Clone, Copy, Debug)]
+pub struct TouchEvent {
+    pub phase : TouchPhase,
+    pub x : f32,
+    pub y : f32,
+    pub time : Instant,
+}
+
+// Reads a touch screen through its evdev device without blocking. Only the
+// primary contact is tracked, using the single touch events (BTN_TOUCH,
+// ABS_X/ABS_Y) that multi touch drivers emit as well.
+pub struct TouchScreen {
+    device : File,
+    path : String,
+    // factors from device units to screen pixels
+    scale : [f32; 2],
+    // bytes of an incomplete event from the last read
+    buffer : Vec<u8>,
+    // state collected until the next SYN_REPORT
+    x : i32,
+    y : i32,
+    touching : bool,
+    moved : bool,
+    // state of the last reported event
+    reportedTouching : bool,
+}
+
+impl TouchScreen {
+
+    // deviceSize is the maximum coordinate the device reports per axis,
+    // None if it reports screen pixels.
+    pub fn Open(path : & str, deviceSize : Option<[f32; 2]>, screenSize : [f32; 2]) -> Result<TouchScreen> {
+
+        let device = match OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK).open(path) {
+            Ok(device) => device,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Err(Error::FileNotFound(path.to_owned())),
+            Err(err) => return Err(Error::Io(path.to_owned(), err)),
+        };
+
+        let scale = match deviceSize {
+            Some(size) => [ screenSize[0] / size[0], screenSize[1] / size[1] ],
+            None => [1.0, 1.0],
+        };
+
+        Ok(TouchScreen {
+            device: device,
+            path: path.to_owned(),
+            scale: scale,
+            buffer: vec![],
+            x: 0,
+            y: 0,
+            touching: false,
+            moved: false,
+            reportedTouching: false,
+        })
+    }
+
+    // Returns the touch events since the last call
+    pub fn Poll(& mut self) -> Result<Vec<TouchEvent>> {
+
+        let mut chunk = [0u8; 1024];
+        loop {
+            match self.device.read(& mut chunk) {
+                Ok(0) => break,
+                Ok(count) => self.buffer.extend_from_slice(& chunk[.. count]),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(Error::Io(self.path.clone(), err)),
+            }
+        }
+
+        // struct input_event: a timeval followed by type u16, code u16 and
+        // value i32. The timeval is 8 bytes on the 32 bit Pi, 16 on 64 bit.
+        let timeSize = mem::size_of::<libc::timeval>();
+        let eventSize = timeSize + 8;
+
+        let mut events = vec![];
+        let complete = self.buffer.len() / eventSize * eventSize;
+
+        for raw in self.buffer[.. complete].to_vec().chunks(eventSize) {
+            let eventType = ReadU16(raw, timeSize);
+            let code = ReadU16(raw, timeSize + 2);
+            let value = ReadU16(raw, timeSize + 4) as u32 | (ReadU16(raw, timeSize + 6) as u32) << 16;
+
+            if let Some(event) = self.Process(eventType, code, value as i32) {
+                events.push(event);
+            }
+        }
+
+        self.buffer.drain(.. complete);
+
+        Ok(events)
+    }
+
+    fn Process(& mut self, eventType : u16, code : u16, value : i32) -> Option<TouchEvent> {
+
+        match (eventType, code) {
+            (EV_KEY, BTN_TOUCH) => self.touching = value != 0,
+            (EV_ABS, ABS_X) => { self.x = value; self.moved = true; },
+            (EV_ABS, ABS_Y) => { self.y = value; self.moved = true; },
+            (EV_SYN, SYN_REPORT) => return self.Report(),
+            _ => (),
+        }
+
+        None
+    }
+
+    fn Report(& mut self) -> Option<TouchEvent> {
+
+        let phase = match (self.reportedTouching, self.touching) {
+            (false, true) => TouchPhase::Down,
+            (true, true) if self.moved => TouchPhase::Move,
+            (true, false) => TouchPhase::Up,
+            _ => return None,
+        };
+
+        self.reportedTouching = self.touching;
+        self.moved = false;
+
+        Some(TouchEvent {
+            phase: phase,
+            x: self.x as f32 * self.scale[0],
+            y: self.y as f32 * self.scale[1],
+            time: Instant::now(),
+        })
+    }
+}
+
+fn ReadU16(raw : &[u8], offset : usize) -> u16 {
+    raw[offset] as u16 | (raw[offset + 1] as u16) << 8
+}
+
+////////////////////////////////////
+// Gestures
+
+// direction the finger moved
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SwipeDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Gesture {
+    Tap { x : f32, y : f32 },
+    Swipe { direction : SwipeDirection, x : f32, y : f32 },
+}
+
+// Turns touch events into taps and swipes. Gloved fingers on a vibrating
+// bike are imprecise, so the thresholds are generous.
+pub struct GestureRecognizer {
+    // where and when the finger went down
+    start : Option<TouchEvent>,
+    // taps may move this far in pixels
+    tapSlop : f32,
+    tapMaxTime : Duration,
+    swipeMinDistance : f32,
+    swipeMaxTime : Duration,
+}
+
+impl GestureRecognizer {
+
+    pub fn new() -> GestureRecognizer {
+        GestureRecognizer {
+            start: None,
+            tapSlop: 20.0,
+            tapMaxTime: Duration::from_millis(400),
+            swipeMinDistance: 100.0,
+            swipeMaxTime: Duration::from_millis(800),
+        }
+    }
+
+    pub fn WithTapSlop(mut self, tapSlop : f32) -> GestureRecognizer {
+        self.tapSlop = tapSlop;
+        self
+    }
+
+    pub fn WithSwipeDistance(mut self, swipeMinDistance : f32) -> GestureRecognizer {
+        self.swipeMinDistance = swipeMinDistance;
+        self
+    }
+
+    // Returns the gesture completed by the event, if any
+    pub fn Feed(& mut self, event : & TouchEvent) -> Option<Gesture> {
+
+        match event.phase {
+            TouchPhase::Down => {
+                self.start = Some(*event);
+                None
+            },
+            TouchPhase::Move => None,
+            TouchPhase::Up => {
+                let start = self.start.take()?;
+                let duration = event.time - start.time;
+                let dx = event.x - start.x;
+                let dy = event.y - start.y;
+                let distance = (dx * dx + dy * dy).sqrt();
+
+                if distance <= self.tapSlop && duration <= self.tapMaxTime {
+                    return Some(Gesture::Tap { x: start.x, y: start.y });
+                }
+
+                if distance >= self.swipeMinDistance && duration <= self.swipeMaxTime {
+                    let direction = if dx.abs() > dy.abs() {
+                        if dx < 0.0 { SwipeDirection::Left } else { SwipeDirection::Right }
+                    } else {
+                        if dy < 0.0 { SwipeDirection::Up } else { SwipeDirection::Down }
+                    };
+                    return Some(Gesture::Swipe { direction: direction, x: start.x, y: start.y });
+                }
+
+                None
+            },
+        }
+    }
+}
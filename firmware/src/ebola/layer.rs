@@ -0,0 +1,172 @@
+#![allow(non_snake_case)]
+
+use std::sync::mpsc;
+use std::sync::mpsc::{ Receiver, Sender, TryRecvError };
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use opengles::glesv2 as gl;
+
+use app;
+use assets::AssetManager;
+use error::{ Error, Result };
+use renderer;
+use renderer::RenderContext;
+use scheduler::FrameScheduler;
+use stats;
+use { CreateLayerGLContext, LayerDescriptor, LayerGLContext };
+
+// Everything the hooks of a layer work with. Lives on the layer's thread.
+pub struct LayerContext {
+    pub render : RenderContext,
+    pub scheduler : FrameScheduler,
+    pub assets : AssetManager,
+}
+
+// Content of a layer drawn by its own thread (see SpawnLayer). The hooks
+// run on that thread with the layer's GL context current.
+pub trait LayerApplication {
+
+    // sent by the main thread through the LayerHandle
+    type Message : Send + 'static;
+
+    // Called once before the first frame, loads the GL resources of the
+    // layer.
+    fn init(& mut self, ctx : & mut LayerContext) -> Result<()>;
+
+    // Called every frame with the time passed since the previous one.
+    fn update(& mut self, ctx : & mut LayerContext, dt : Duration);
+
+    // Called before update for each message received since the last frame.
+    fn handle_message(& mut self, _ctx : & mut LayerContext, _message : Self::Message) {
+    }
+}
+
+enum LayerCommand<M> {
+    Message(M),
+    Quit,
+}
+
+// Main thread side of a layer. Dropping it stops the layer thread and waits
+// for it, so it has to be dropped while the main GLContext is still alive,
+// e.g. in Application::shutdown.
+pub struct LayerHandle<M> {
+    sender : Sender<LayerCommand<M>>,
+    thread : Option<JoinHandle<()>>,
+}
+
+impl<M> LayerHandle<M> {
+
+    // Queues the message for the next frame of the layer. False if the
+    // layer thread is gone.
+    pub fn Send(& self, message : M) -> bool {
+        self.sender.send(LayerCommand::Message(message)).is_ok()
+    }
+}
+
+impl<M> Drop for LayerHandle<M> {
+    fn drop(& mut self) {
+        let _ = self.sender.send(LayerCommand::Quit);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Runs a layer on its own thread with its own EGL context and frame loop,
+// so it keeps updating while the main loop stalls, e.g. while assets load
+// or the main surface waits for its swap. The layer starts with a
+// transparent clear color.
+// GL objects can't move between contexts, so the application is created on
+// the layer thread by the factory. Returns once the layer is initialized.
+pub fn SpawnLayer<A, F>(descriptor : LayerDescriptor, dataPath : & str, targetFps : u32, factory : F) -> Result<LayerHandle<A::Message>>
+    where A : LayerApplication, F : FnOnce() -> A + Send + 'static {
+
+    let (sender, receiver) = mpsc::channel();
+    let (initSender, initReceiver) = mpsc::channel();
+    let dataPath = dataPath.to_owned();
+
+    let thread = thread::spawn(move || {
+
+        let glCtx = match CreateLayerGLContext(& descriptor) {
+            Ok(glCtx) => glCtx,
+            Err(err) => {
+                let _ = initSender.send(Err(err));
+                return;
+            }
+        };
+
+        gl::viewport(0, 0, glCtx.Width(), glCtx.Height());
+
+        let mut ctx = LayerContext {
+            render: RenderContext::new([0.0, 0.0, 0.0, 0.0]),
+            scheduler: FrameScheduler::new(targetFps),
+            assets: AssetManager::new(& dataPath),
+        };
+
+        let mut app = factory();
+
+        if let Err(err) = app.init(& mut ctx) {
+            let _ = initSender.send(Err(err));
+            return;
+        }
+        let _ = initSender.send(Ok(()));
+
+        RunLayer(& mut app, & glCtx, & mut ctx, & receiver);
+
+        // GL objects have to be deleted before the context is destroyed
+        drop(app);
+        drop(ctx);
+        drop(glCtx);
+    });
+
+    match initReceiver.recv() {
+        Ok(Ok(())) => Ok(LayerHandle {
+            sender: sender,
+            thread: Some(thread),
+        }),
+        Ok(Err(err)) => {
+            let _ = thread.join();
+            Err(err)
+        },
+        Err(_) => {
+            let _ = thread.join();
+            Err(Error::LayerThreadPanicked)
+        },
+    }
+}
+
+// Frame loop of the layer thread, returns when the handle was dropped.
+fn RunLayer<A : LayerApplication>(app : & mut A, glCtx : & LayerGLContext, ctx : & mut LayerContext, receiver : & Receiver<LayerCommand<A::Message>>) {
+
+    let mut stateCache = renderer::RenderStateCache::new();
+
+    loop {
+        let deltaTime = ctx.scheduler.BeginFrame();
+
+        loop {
+            match receiver.try_recv() {
+                Ok(LayerCommand::Message(message)) => app.handle_message(ctx, message),
+                Ok(LayerCommand::Quit) | Err(TryRecvError::Disconnected) => return,
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+
+        app.update(ctx, deltaTime);
+
+        if !ctx.scheduler.ShouldRender() {
+            continue;
+        }
+
+        app::DrawFrame(& ctx.render, & mut stateCache);
+        glCtx.SwapBuffers();
+
+        ctx.scheduler.FrameRendered();
+
+        // the layer keeps no statistics, the counters are per thread and
+        // would only grow
+        stats::TakeFrameCounters();
+    }
+}
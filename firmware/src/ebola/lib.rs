@@ -1,15 +1,37 @@
 #![feature(duration_as_u128)]
 #![allow(non_snake_case)]
 
+pub mod animation;
+pub mod app;
+pub mod assets;
+pub mod atlas;
+pub mod backlight;
+pub mod cache;
+pub mod chart;
+pub mod duration;
+pub mod error;
+pub mod etc1;
+pub mod input;
+pub mod layer;
+pub mod pages;
 pub mod texture;
+pub mod theme;
+pub mod preprocessor;
 pub mod renderer;
+pub mod resources;
+pub mod scheduler;
+pub mod shapes;
+pub mod signals;
+pub mod stats;
+
+pub use error::{ Error, Result };
 
 extern crate videocore;
 extern crate egl;
 extern crate opengles;
+extern crate libc;
 
 use std::ptr;
-use std::time::Instant;
 
 use videocore::bcm_host;
 use videocore::dispmanx;
@@ -35,7 +57,64 @@ pub struct GLContext {
     pub surface: EGLSurface
 }
 
-pub fn CreateRenderWindow() -> Window {
+impl GLContext {
+
+    pub fn SwapBuffers(& self) {
+        egl::swap_buffers(self.display, self.surface);
+    }
+}
+
+impl Drop for GLContext {
+    fn drop(& mut self) {
+        // all GL objects should be gone by now
+        resources::ReportLeaks();
+
+        egl::make_current(self.display, egl::EGL_NO_SURFACE, egl::EGL_NO_SURFACE, egl::EGL_NO_CONTEXT);
+        egl::destroy_surface(self.display, self.surface);
+        egl::destroy_context(self.display, self.context);
+        egl::terminate(self.display);
+    }
+}
+
+// How the pixels of a layer are blended with the layers below
+#[derive(Clone, Copy, PartialEq)]
+pub enum LayerAlpha {
+    // the whole layer uses the descriptor's opacity
+    Fixed,
+    // the alpha channel of the rendered frame, e.g. to leave parts of a
+    // warning layer transparent
+    PerPixel,
+    // same as PerPixel, for frames rendered with premultiplied alpha
+    PerPixelPremultiplied,
+}
+
+// Placement of a dispmanx element. Elements on a higher layer are composited
+// on top, every element is updated independently by the display hardware.
+pub struct LayerDescriptor {
+    pub layer : i32,
+    // area on screen, None covers the whole screen
+    pub rect : Option<Rect>,
+    pub opacity : u8,
+    pub alpha : LayerAlpha,
+}
+
+impl Default for LayerDescriptor {
+    fn default() -> LayerDescriptor {
+        LayerDescriptor {
+            layer: 3,
+            rect: None,
+            opacity: 255,
+            alpha: LayerAlpha::Fixed,
+        }
+    }
+}
+
+// Full screen window on the default layer, used for the main surface
+pub fn CreateRenderWindow() -> Result<Window> {
+    CreateLayerWindow(& LayerDescriptor::default())
+}
+
+pub fn CreateLayerWindow(descriptor : & LayerDescriptor) -> Result<Window> {
     
     // open the display
     let display = dispmanx::display_open(0);
@@ -45,16 +124,24 @@ pub fn CreateRenderWindow() -> Window {
     // query the screen resolution of the connected screen
     let screenRes = match bcm_host::graphics_get_display_size(0) {
         Some(x) => x,
-        None => panic!("bcm_host::init() has not been called prior to creating a window.")
+        None => return Err(Error::Window("bcm_host::init() has not been called prior to creating a window."))
     };
 
     println!("Screen Resolution: {}x{}", screenRes.width, screenRes.height);
 
-    let mut dest_rect = Rect {
-        x:      0,
-        y:      0,
-        width:  screenRes.width as i32,
-        height: screenRes.height as i32,
+    let mut dest_rect = match descriptor.rect {
+        Some(ref rect) => Rect {
+            x:      rect.x,
+            y:      rect.y,
+            width:  rect.width,
+            height: rect.height,
+        },
+        None => Rect {
+            x:      0,
+            y:      0,
+            width:  screenRes.width as i32,
+            height: screenRes.height as i32,
+        }
     };
 
     let mut src_rect = Rect {
@@ -64,15 +151,21 @@ pub fn CreateRenderWindow() -> Window {
         height: 0,
     };
 
+    let alphaFlags = match descriptor.alpha {
+        LayerAlpha::Fixed => FlagsAlpha::FIXED_ALL_PIXELS,
+        LayerAlpha::PerPixel => FlagsAlpha::FROM_SOURCE,
+        LayerAlpha::PerPixelPremultiplied => FlagsAlpha::FROM_SOURCE | FlagsAlpha::PREMULT,
+    };
+
     let mut alpha = VCAlpha { 
-        flags: FlagsAlpha::FIXED_ALL_PIXELS,
-        opacity: 255,
+        flags: alphaFlags,
+        opacity: descriptor.opacity as u32,
         mask: 0,
     };
 
     let element = dispmanx::element_add(update_hndl, 
                                         display, 
-                                        3, // layer to draw on
+                                        descriptor.layer,
                                         & mut dest_rect, 
                                         0, 
                                         & mut src_rect, 
@@ -84,14 +177,38 @@ pub fn CreateRenderWindow() -> Window {
     // submit display setup
     dispmanx::update_submit_sync(update_hndl);
 
-    Window { 
+    Ok(Window { 
         element:    element,
-        width:      screenRes.width as i32,
-        height:     screenRes.height as i32,
-    }
+        width:      dest_rect.width,
+        height:     dest_rect.height,
+    })
+}
+
+// Takes the element of the window off the screen.
+pub fn RemoveLayerWindow(window : & Window) {
+    let update_hndl = dispmanx::update_start(0);
+    dispmanx::element_remove(update_hndl, window.element);
+    dispmanx::update_submit_sync(update_hndl);
 }
 
-pub fn InitEGL(window : & mut Window) -> GLContext {
+pub fn InitEGL(window : & mut Window) -> Result<GLContext> {
+
+    let (egl_display, egl_config, egl_context, egl_surface) = ConnectEGL(window)?;
+
+    let supportsShaderCompiler = gl::get_booleanv(gl::GL_SHADER_COMPILER);
+    println!("Supports shader compiler: {}", supportsShaderCompiler);
+
+    Ok(GLContext {
+        config: egl_config,
+        context: egl_context,
+        display: egl_display,
+        surface: egl_surface,
+    })
+}
+
+// Creates a context without shared objects and a surface for the window and
+// makes them current on the calling thread.
+fn ConnectEGL(window : & mut Window) -> Result<(EGLDisplay, EGLConfig, EGLContext, EGLSurface)> {
     
     let context_attr = [ egl::EGL_CONTEXT_CLIENT_VERSION, 2, egl::EGL_NONE ];
     
@@ -104,71 +221,116 @@ pub fn InitEGL(window : & mut Window) -> GLContext {
 
     let egl_display = match egl::get_display(egl::EGL_DEFAULT_DISPLAY) {
         Some(x) => x,
-        None    => panic!("Failed to get EGL display")
+        None    => return Err(Error::EGL("Failed to get EGL display"))
     };
 
     if !egl::initialize(egl_display, &mut 0i32, &mut 0i32) {
-        panic!("Failed to initialize EGL");
+        return Err(Error::EGL("Failed to initialize EGL"));
     }
 
     // select first config
     let egl_config = match egl::choose_config(egl_display, & config_attr, 1) {
         Some(x)     => x,
-        None        => panic!("Failed to find compatible EGL config")
+        None        => return Err(Error::EGL("Failed to find compatible EGL config"))
     };
 
     if !egl::bind_api(egl::EGL_OPENGL_ES_API) {
-        panic!("Failed to bind OpenGL ES API");
+        return Err(Error::EGL("Failed to bind OpenGL ES API"));
     }
 
     // create the egl context
     let egl_context = match egl::create_context(egl_display, egl_config, egl::EGL_NO_CONTEXT, &context_attr) {
         Some(context)   => context,
-        None            => panic!("Failed to create EGL context")
+        None            => return Err(Error::EGL("Failed to create EGL context"))
     };
 
     let egl_surface = match egl::create_window_surface(egl_display, egl_config, window as *mut _ as EGLNativeDisplayType, &[]) {
         Some(surface)   => surface,
-        None            => panic!("Failed to create EGL surface")
+        None            => {
+            egl::destroy_context(egl_display, egl_context);
+            return Err(Error::EGL("Failed to create EGL surface"));
+        }
     };
 
     // activate context
     if !egl::make_current(egl_display, egl_surface, egl_surface, egl_context) {
-        panic!("Failed to activate EGL context");
+        egl::destroy_surface(egl_display, egl_surface);
+        egl::destroy_context(egl_display, egl_context);
+        return Err(Error::EGL("Failed to activate EGL context"));
     }
 
-    let supportsShaderCompiler = gl::get_booleanv(gl::GL_SHADER_COMPILER);
-    println!("Supports shader compiler: {}", supportsShaderCompiler);
+    Ok((egl_display, egl_config, egl_context, egl_surface))
+}
 
-    GLContext {
-        config: egl_config,
-        context: egl_context,
-        display: egl_display,
-        surface: egl_surface,
+
+// Sets how many vertical blanks eglSwapBuffers waits for. 1 syncs to the
+// display refresh, 0 disables vsync.
+pub fn SetSwapInterval(glCtx : & GLContext, interval : i32) -> Result<()> {
+    if !egl::swap_interval(glCtx.display, interval) {
+        return Err(Error::EGL("Failed to set swap interval"));
     }
+    Ok(())
 }
 
+// A window on its own dispmanx layer with its own EGL context. The context
+// shares no GL objects with the main one, so the layer can be drawn by
+// another thread (see layer::SpawnLayer). It's current on the thread that
+// created it and has to be dropped there, before the main GLContext
+// terminates the display.
+pub struct LayerGLContext {
+    // EGL keeps a pointer to the window, so it must not move
+    window : Box<Window>,
+    context : EGLContext,
+    display : EGLDisplay,
+    surface : EGLSurface,
+}
 
-pub fn RunMainLoop(renderCtx : renderer::RenderContext, glCtx : GLContext) {
-    
-    let screen_res = bcm_host::graphics_get_display_size(0).unwrap();
+impl LayerGLContext {
 
-    gl::viewport(0, 0, screen_res.width as i32, screen_res.height as i32);
-    
-    let mut delta_time_ms = 0;
-    
-    loop {
-
-        let time_now = Instant::now();
-   
-        gl::clear_color(renderCtx.clearColor[0] , renderCtx.clearColor[1], renderCtx.clearColor[2], renderCtx.clearColor[3]);
-        gl::clear(gl::GL_COLOR_BUFFER_BIT);
- 
-        renderer::Render(& renderCtx.shaderStages, & renderCtx.renderCommands);
-        // swap
-        egl::swap_buffers(glCtx.display, glCtx.surface);
-
-        delta_time_ms = time_now.elapsed().as_millis();
-        //println!("DeltaTime: {}", delta_time_ms as u64);
+    pub fn Width(& self) -> i32 {
+        self.window.width
+    }
+
+    pub fn Height(& self) -> i32 {
+        self.window.height
+    }
+
+    pub fn SwapBuffers(& self) {
+        egl::swap_buffers(self.display, self.surface);
+    }
+}
+
+impl Drop for LayerGLContext {
+    fn drop(& mut self) {
+        // the display belongs to the main GLContext and stays initialized
+        egl::make_current(self.display, egl::EGL_NO_SURFACE, egl::EGL_NO_SURFACE, egl::EGL_NO_CONTEXT);
+        egl::destroy_surface(self.display, self.surface);
+        egl::destroy_context(self.display, self.context);
+
+        RemoveLayerWindow(& self.window);
     }
-}
\ No newline at end of file
+}
+
+// Creates a window on the described layer and a context for it, current on
+// the calling thread.
+pub fn CreateLayerGLContext(descriptor : & LayerDescriptor) -> Result<LayerGLContext> {
+
+    let mut window = Box::new(CreateLayerWindow(descriptor)?);
+
+    // the window has no Drop, an element left behind would cover the layers
+    // below with undefined pixels
+    let (display, _, context, surface) = match ConnectEGL(& mut window) {
+        Ok(egl) => egl,
+        Err(err) => {
+            RemoveLayerWindow(& window);
+            return Err(err);
+        }
+    };
+
+    Ok(LayerGLContext {
+        window: window,
+        context: context,
+        display: display,
+        surface: surface,
+    })
+}
@@ -0,0 +1,262 @@
+#![allow(non_snake_case)]
+
+use std::time::Duration;
+
+use animation::{ Easing, Timeline };
+use error::{ Error, Result };
+use renderer::RenderContext;
+
+// How the new page replaces the old one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transition {
+    None,
+    // the new page pushes the old one out to the left, as after a swipe to
+    // the left
+    SlideLeft,
+    SlideRight,
+    SlideUp,
+    SlideDown,
+}
+
+// A screen of the application, made of render passes of the main
+// RenderContext. Passes of hidden pages are disabled.
+struct Page {
+    name : String,
+    passes : Vec<String>,
+}
+
+struct ActiveTransition {
+    from : usize,
+    // start offset of the new page in screen sizes, the old page leaves in
+    // the opposite direction
+    direction : [f32; 2],
+    timeline : Timeline,
+}
+
+// Keeps a stack of pages, the top one is shown. Pages are moved with a
+// uniform offset (RenderPass::offset) while they slide in and out.
+pub struct PageManager {
+    // in the order they were added, which is also the order to swipe through
+    pages : Vec<Page>,
+    // indices into pages, the last one is shown
+    stack : Vec<usize>,
+    transition : Option<ActiveTransition>,
+    screenSize : [f32; 2],
+    transitionTime : Duration,
+    // the passes don't match the pages yet
+    dirty : bool,
+}
+
+impl PageManager {
+
+    pub fn new(screenSize : [f32; 2]) -> PageManager {
+        PageManager {
+            pages: vec![],
+            stack: vec![],
+            transition: None,
+            screenSize: screenSize,
+            transitionTime: Duration::from_millis(300),
+            dirty: true,
+        }
+    }
+
+    pub fn WithTransitionTime(mut self, transitionTime : Duration) -> PageManager {
+        self.transitionTime = transitionTime;
+        self
+    }
+
+    // The first page added is shown first.
+    pub fn AddPage(& mut self, name : & str, passes : &[& str]) {
+        self.pages.push(Page {
+            name: name.to_owned(),
+            passes: passes.iter().map(|pass| (*pass).to_owned()).collect(),
+        });
+
+        if self.stack.is_empty() {
+            self.stack.push(self.pages.len() - 1);
+        }
+        self.dirty = true;
+    }
+
+    pub fn Current(& self) -> Option<& str> {
+        self.stack.last().map(|& idx| self.pages[idx].name.as_str())
+    }
+
+    pub fn Depth(& self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn IsTransitioning(& self) -> bool {
+        self.transition.is_some()
+    }
+
+    // Shows the page on top of the current one, Pop returns to it.
+    pub fn Push(& mut self, name : & str, transition : Transition) -> Result<()> {
+        let idx = self.Find(name)?;
+        let from = self.stack.last().cloned();
+
+        self.stack.push(idx);
+        self.Begin(from, transition);
+        Ok(())
+    }
+
+    // Returns to the previous page. The last page is never popped.
+    pub fn Pop(& mut self, transition : Transition) -> bool {
+        if self.stack.len() < 2 {
+            return false;
+        }
+
+        let from = self.stack.pop();
+        self.Begin(from, transition);
+        true
+    }
+
+    // Replaces the current page
+    pub fn SwitchTo(& mut self, name : & str, transition : Transition) -> Result<()> {
+        let idx = self.Find(name)?;
+        let from = self.stack.pop();
+
+        self.stack.push(idx);
+        self.Begin(from, transition);
+        Ok(())
+    }
+
+    // Drops the whole stack and shows the page
+    pub fn ResetTo(& mut self, name : & str, transition : Transition) -> Result<()> {
+        let idx = self.Find(name)?;
+        let from = self.stack.last().cloned();
+
+        self.stack = vec![idx];
+        self.Begin(from, transition);
+        Ok(())
+    }
+
+    // Switches to the page added after the current one, false on the last.
+    pub fn Next(& mut self, transition : Transition) -> bool {
+        self.SwitchBy(1, transition)
+    }
+
+    // Switches to the page added before the current one, false on the first.
+    pub fn Previous(& mut self, transition : Transition) -> bool {
+        self.SwitchBy(-1, transition)
+    }
+
+    // Advances the transition and updates the passes of the render context.
+    // Returns whether anything on screen changed.
+    pub fn Update(& mut self, dt : Duration, render : & mut RenderContext) -> bool {
+
+        let mut changed = self.dirty;
+        self.dirty = false;
+
+        if let Some(ref mut transition) = self.transition {
+            transition.timeline.Update(dt);
+            changed = true;
+        }
+
+        if changed {
+            self.Apply(render);
+        }
+
+        if self.transition.as_ref().map_or(false, |transition| !transition.timeline.IsPlaying()) {
+            self.transition = None;
+            // leaves the old page disabled and the new one without offset
+            self.Apply(render);
+        }
+
+        changed
+    }
+
+    fn SwitchBy(& mut self, step : isize, transition : Transition) -> bool {
+        let current = match self.stack.last() {
+            Some(& idx) => idx as isize,
+            None => return false,
+        };
+
+        let next = current + step;
+        if next < 0 || next >= self.pages.len() as isize {
+            return false;
+        }
+
+        let from = self.stack.pop();
+        self.stack.push(next as usize);
+        self.Begin(from, transition);
+        true
+    }
+
+    fn Find(& self, name : & str) -> Result<usize> {
+        match self.pages.iter().position(|page| page.name == name) {
+            Some(idx) => Ok(idx),
+            None => Err(Error::PageNotFound(name.to_owned())),
+        }
+    }
+
+    // A transition that is still running is cut short, the new one starts
+    // from the page it was heading to.
+    fn Begin(& mut self, from : Option<usize>, transition : Transition) {
+
+        self.dirty = true;
+
+        let direction = match transition {
+            Transition::None => None,
+            Transition::SlideLeft => Some([1.0, 0.0]),
+            Transition::SlideRight => Some([-1.0, 0.0]),
+            Transition::SlideUp => Some([0.0, 1.0]),
+            Transition::SlideDown => Some([0.0, -1.0]),
+        };
+
+        self.transition = match (from, direction) {
+            (Some(from), Some(direction)) if Some(& from) != self.stack.last() => {
+                let mut timeline = Timeline::new()
+                    .WithSegment("progress", Duration::from_millis(0), self.transitionTime, 0.0, 1.0, Easing::CubicInOut);
+                timeline.Play();
+
+                Some(ActiveTransition {
+                    from: from,
+                    direction: direction,
+                    timeline: timeline,
+                })
+            },
+            _ => None,
+        };
+    }
+
+    fn Apply(& self, render : & mut RenderContext) {
+
+        let current = match self.stack.last() {
+            Some(& idx) => idx,
+            None => return,
+        };
+
+        for (idx, page) in self.pages.iter().enumerate() {
+            if idx == current {
+                continue;
+            }
+
+            for pass in page.passes.iter() {
+                render.SetPassEnabled(pass, false);
+            }
+        }
+
+        // the old page slides out while the new one slides in
+        let mut offset = [0.0, 0.0];
+        if let Some(ref transition) = self.transition {
+            let progress = transition.timeline.Value("progress");
+            let d = transition.direction;
+
+            offset = [ d[0] * (1.0 - progress) * self.screenSize[0], d[1] * (1.0 - progress) * self.screenSize[1] ];
+            self.ShowPage(render, transition.from, [ -d[0] * progress * self.screenSize[0], -d[1] * progress * self.screenSize[1] ]);
+        }
+
+        // last, so passes shared with the old page stay visible
+        self.ShowPage(render, current, offset);
+    }
+
+    fn ShowPage(& self, render : & mut RenderContext, idx : usize, offset : [f32; 2]) {
+        for pass in self.pages[idx].passes.iter() {
+            if let Some(pass) = render.PassMut(pass) {
+                pass.enabled = true;
+                pass.offset = offset;
+            }
+        }
+    }
+}
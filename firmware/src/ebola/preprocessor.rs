@@ -0,0 +1,98 @@
+#![allow(non_snake_case)]
+
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+use error::{ Error, Result };
+
+// Reads the shader source and prepares it for compilation:
+//
+// - '#include "file.glsl"' is replaced by the content of the file. The path is
+//   relative to the directory of the including file. Includes may be nested.
+// - every entry of defines is inserted as '#define', either as "NAME" or as
+//   "NAME=VALUE". They are placed behind a '#version' directive if present.
+//
+// This lets shaders share code and a single source yield multiple variants
+// through '#ifdef' blocks.
+pub fn PreprocessShader(path : & str, defines : &[& str]) -> Result<String> {
+
+    let mut includeStack = vec![];
+    let source = ExpandIncludes(Path::new(path), & mut includeStack)?;
+
+    let mut header = String::new();
+    for define in defines.iter() {
+        match define.find('=') {
+            Some(idx) => header.push_str(& format!("#define {} {}\n", & define[.. idx], & define[idx + 1 ..])),
+            None => header.push_str(& format!("#define {}\n", define)),
+        }
+    }
+
+    // '#version' has to stay the first statement
+    if source.trim_left().starts_with("#version") {
+        let versionEnd = source.find('\n').map_or(source.len(), |idx| idx + 1);
+        Ok(format!("{}{}{}", & source[.. versionEnd], header, & source[versionEnd ..]))
+    } else {
+        Ok(format!("{}{}", header, source))
+    }
+}
+
+fn ExpandIncludes(path : & Path, includeStack : & mut Vec<PathBuf>) -> Result<String> {
+
+    let pathStr = path.to_string_lossy().into_owned();
+
+    if !path.exists() {
+        return Err(Error::FileNotFound(pathStr));
+    }
+
+    if includeStack.iter().any(|p| p == path) {
+        return Err(Error::ShaderIncludeCycle(pathStr));
+    }
+
+    let source = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) => return Err(Error::Io(pathStr, err))
+    };
+
+    includeStack.push(path.to_path_buf());
+
+    let baseDir = path.parent().unwrap_or(Path::new("."));
+    let mut expanded = String::new();
+
+    for line in source.lines() {
+        match ParseInclude(line) {
+            Some(includePath) => {
+                let included = ExpandIncludes(& baseDir.join(includePath), includeStack)?;
+                expanded.push_str(& included);
+                if !included.ends_with('\n') {
+                    expanded.push('\n');
+                }
+            },
+            None => {
+                expanded.push_str(line);
+                expanded.push('\n');
+            }
+        }
+    }
+
+    includeStack.pop();
+
+    Ok(expanded)
+}
+
+// Returns the file name of an '#include "file"' line
+fn ParseInclude(line : & str) -> Option<& str> {
+
+    let line = line.trim();
+
+    if !line.starts_with("#include") {
+        return None;
+    }
+
+    let rest = line["#include".len() ..].trim();
+
+    if rest.len() >= 2 && rest.starts_with('"') && rest.ends_with('"') {
+        Some(& rest[1 .. rest.len() - 1])
+    } else {
+        None
+    }
+}
@@ -2,14 +2,165 @@
 
 use std::path::Path;
 use std::fs;
+use std::mem;
+use std::ptr;
+use std::cell::Cell;
+use std::rc::Rc;
 use std::time::Instant;
 
+use libc;
 use opengles::glesv2 as gl;
 
+use cache;
+use cache::ProgramBinaryExt;
+use error::{ Error, Result };
+use preprocessor;
+use resources;
+use resources::ResourceKind;
+use stats;
+use texture;
+use texture::Texture;
+
 pub struct RenderContext {
-    pub shaderStages: Vec<ShaderStage>,
+    // color the frame is cleared to before the first pass
     pub clearColor : [f32;4],
-    pub renderCommands : Vec<Vec<RenderCommand>>,
+    // sorted by RenderPass::order
+    passes : Vec<RenderPass>,
+}
+
+impl RenderContext {
+
+    pub fn new(clearColor : [f32;4]) -> RenderContext {
+        RenderContext {
+            clearColor: clearColor,
+            passes: vec![],
+        }
+    }
+
+    // Inserts the pass according to its order. Passes with the same order
+    // are rendered in the order they were added.
+    pub fn AddPass(& mut self, pass : RenderPass) {
+        let idx = self.passes.iter().position(|p| p.order > pass.order).unwrap_or(self.passes.len());
+        self.passes.insert(idx, pass);
+    }
+
+    pub fn RemovePass(& mut self, name : & str) -> Option<RenderPass> {
+        match self.passes.iter().position(|p| p.name == name) {
+            Some(idx) => Some(self.passes.remove(idx)),
+            None => None,
+        }
+    }
+
+    pub fn Pass(& self, name : & str) -> Option<& RenderPass> {
+        self.passes.iter().find(|p| p.name == name)
+    }
+
+    pub fn PassMut(& mut self, name : & str) -> Option<& mut RenderPass> {
+        self.passes.iter_mut().find(|p| p.name == name)
+    }
+
+    // Returns false if there is no pass with that name.
+    pub fn SetPassEnabled(& mut self, name : & str, enabled : bool) -> bool {
+        match self.PassMut(name) {
+            Some(pass) => {
+                pass.enabled = enabled;
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn Render(& self, stateCache : & mut RenderStateCache) {
+        for pass in self.passes.iter().filter(|p| p.enabled) {
+            pass.Execute(stateCache);
+        }
+    }
+}
+
+////////////////////////////////////
+// Render Pass
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ClearPolicy {
+    // draw on top of the previous passes
+    None,
+    // clear the color buffer before drawing the pass
+    Color([f32;4]),
+}
+
+// uniforms set by the pass itself, commands don't bind them
+const PASS_OFFSET_UNIFORM : & str = "u_offset";
+const PASS_UNIFORMS : [& str; 1] = [ PASS_OFFSET_UNIFORM ];
+
+// A named list of commands drawn with the same shader stage, e.g. "world",
+// "ui" or "overlay".
+pub struct RenderPass {
+    pub name : String,
+    pub shaderStage : Rc<ShaderStage>,
+    pub commands : Vec<RenderCommand>,
+    pub clear : ClearPolicy,
+    // passes are rendered in ascending order
+    pub order : i32,
+    pub enabled : bool,
+    // moves the whole pass by pixels, e.g. during page transitions. Applied
+    // through the 'u_offset' uniform of projection.glsl.
+    pub offset : [f32; 2],
+    // location of the offset uniform, None if the shader has none
+    offsetLocation : Option<gl::GLint>,
+}
+
+impl RenderPass {
+
+    pub fn new(name : & str, shaderStage : Rc<ShaderStage>, order : i32) -> RenderPass {
+        let offsetLocation = shaderStage.Uniform(PASS_OFFSET_UNIFORM).map(|uniform| uniform.location);
+
+        RenderPass {
+            name: name.to_owned(),
+            shaderStage: shaderStage,
+            commands: vec![],
+            clear: ClearPolicy::None,
+            order: order,
+            enabled: true,
+            offset: [0.0, 0.0],
+            offsetLocation: offsetLocation,
+        }
+    }
+
+    pub fn WithCommands(mut self, commands : Vec<RenderCommand>) -> RenderPass {
+        for cmd in commands.iter() {
+            self.shaderStage.CheckBindings(cmd, & PASS_UNIFORMS);
+        }
+
+        self.commands = commands;
+        self
+    }
+
+    pub fn WithClear(mut self, clear : ClearPolicy) -> RenderPass {
+        self.clear = clear;
+        self
+    }
+
+    fn Execute(& self, stateCache : & mut RenderStateCache) {
+
+        if let ClearPolicy::Color(color) = self.clear {
+            // scissor and color mask also apply to clears
+            stateCache.Apply(& RenderState::default());
+            gl::clear_color(color[0], color[1], color[2], color[3]);
+            gl::clear(gl::GL_COLOR_BUFFER_BIT);
+        }
+
+        self.shaderStage.Use();
+
+        // passes share programs, so the offset is set every time
+        if let Some(location) = self.offsetLocation {
+            gl::uniform2f(location, self.offset[0], self.offset[1]);
+            stats::CountStateChange();
+        }
+
+        for cmd in self.commands.iter() {
+            cmd.Execute(stateCache);
+        }
+    }
 }
 
 pub enum PrimitivesType {
@@ -43,12 +194,24 @@ pub struct AttributeBinding {
     // Handle to the attribute to bind to
     attributeHndl : gl::GLuint,
     
-    // Handle to the data buffer (VBO)
-    dataBufferHndl : gl::GLuint,
+    // The data buffer (VBO), kept alive as long as the binding exists
+    dataBuffer : Rc<GPUBuffer>,
     
-    // Number of components
-    // This is the stride of the data. 
+    // Number of components per vertex
     numComponents: u32,
+
+    // Data type of each component
+    componentType : ComponentType,
+
+    // Whether integer components are mapped to [0,1] / [-1,1]
+    normalized : bool,
+
+    // Distance in bytes between two consecutive vertices.
+    // 0 means tightly packed.
+    stride : u32,
+
+    // Byte offset of the first component inside the buffer
+    offset : u32,
 }
 
 enum UniformTypedData {
@@ -62,11 +225,26 @@ pub struct UniformBinding {
     data : UniformTypedData,
 }
 
+#[derive(Clone)]
+pub struct TextureBinding {
+
+    // handle of the sampler uniform
+    handle : gl::GLint,
+    texture : Rc<Texture>,
+}
+
 pub struct RenderCommand {
     attributeBindings : Vec<AttributeBinding>,
     uniformBindings : Vec<UniformBinding>,
+    // textures sampled by this command, units are assigned in order when binding
+    textureBindings : Vec<TextureBinding>,
     primitiveType : PrimitivesType,
     numVertices: u32,
+    renderState : RenderState,
+    // Byte offset added to the offset of every attribute binding, lets a
+    // command follow data that moves inside its buffer (e.g. a
+    // GPURingBuffer) without rebinding.
+    baseOffset : u32,
 }
 
 impl RenderCommand {
@@ -75,23 +253,71 @@ impl RenderCommand {
         RenderCommand {
             attributeBindings: attributeBindings,
             uniformBindings : uniformBindings,
+            textureBindings: vec![],
             primitiveType: primitiveType,
             numVertices: numVertices,
+            renderState: RenderState::default(),
+            baseOffset: 0,
         }
     }
 
-    pub fn Execute(& self) {
+    pub fn WithRenderState(mut self, renderState : RenderState) -> RenderCommand {
+        self.renderState = renderState;
+        self
+    }
+
+    pub fn WithTextures(mut self, textureBindings : Vec<TextureBinding>) -> Result<RenderCommand> {
+        if textureBindings.len() > texture::MAX_TEXTURE_UNITS {
+            return Err(Error::TooManyTextures(textureBindings.len()));
+        }
+
+        self.textureBindings = textureBindings;
+        Ok(self)
+    }
+
+    // Moves the vertices the command reads to baseOffset bytes behind the
+    // offsets the attributes were bound with.
+    pub fn SetBaseOffset(& mut self, baseOffset : usize) {
+        self.baseOffset = baseOffset as u32;
+    }
+
+    pub fn SetNumVertices(& mut self, numVertices : u32) {
+        self.numVertices = numVertices;
+    }
+
+    pub fn Execute(& self, stateCache : & mut RenderStateCache) {
+        stateCache.Apply(& self.renderState);
         self.Bind();
         self.Draw();
         self.Unbind();
     }
 
     fn Bind(& self) {
+        // interleaved attributes share a buffer, so only rebind when it changes
+        let mut boundBuffer = 0;
+
         for binding in self.attributeBindings.iter() {
             
             gl::enable_vertex_attrib_array(binding.attributeHndl);
-            gl::bind_buffer(gl::GL_ARRAY_BUFFER, binding.dataBufferHndl);
-            gl::vertex_attrib_pointer_offset(binding.attributeHndl, binding.numComponents as gl::GLint, gl::GL_FLOAT, false, 0, 0);
+
+            if binding.dataBuffer.handle != boundBuffer {
+                gl::bind_buffer(gl::GL_ARRAY_BUFFER, binding.dataBuffer.handle);
+                boundBuffer = binding.dataBuffer.handle;
+                stats::CountStateChange();
+            }
+
+            gl::vertex_attrib_pointer_offset(binding.attributeHndl, 
+                                             binding.numComponents as gl::GLint, 
+                                             ComponentTypeToGL(& binding.componentType), 
+                                             binding.normalized, 
+                                             binding.stride as gl::GLint, 
+                                             self.baseOffset + binding.offset);
+        }
+
+        for (unit, binding) in self.textureBindings.iter().enumerate() {
+            binding.texture.Bind(unit as u32);
+            gl::uniform1i(binding.handle, unit as i32);
+            stats::CountStateChange();
         }
 
         for binding in self.uniformBindings.iter() {
@@ -126,6 +352,7 @@ impl RenderCommand {
 
     fn Draw(& self) {
         gl::draw_arrays(ToGL(& self.primitiveType), 0, self.numVertices as gl::GLint);
+        stats::CountDrawCall(self.numVertices);
     }
 
     fn Unbind(& self) {
@@ -138,6 +365,307 @@ impl RenderCommand {
 }
 
 
+////////////////////////////////////
+// Render State
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BlendMode {
+    // no blending, alpha is ignored
+    Opaque,
+    // classic transparency with straight alpha
+    Alpha,
+    // adds the source on top, for glows and highlights
+    Additive,
+    // transparency for textures loaded with premultiplied alpha
+    Premultiplied,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CullMode {
+    None,
+    Front,
+    Back,
+}
+
+// Rectangle in GL window coordinates, (0,0) is the bottom left corner
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ScissorRect {
+    pub x : i32,
+    pub y : i32,
+    pub width : i32,
+    pub height : i32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RenderState {
+    pub blendMode : BlendMode,
+    // only pixels inside the rectangle are written, None disables the test
+    pub scissor : Option<ScissorRect>,
+    pub cullMode : CullMode,
+    // red, green, blue, alpha
+    pub colorMask : [bool; 4],
+}
+
+impl Default for RenderState {
+    fn default() -> RenderState {
+        RenderState {
+            blendMode: BlendMode::Opaque,
+            scissor: None,
+            cullMode: CullMode::None,
+            colorMask: [true, true, true, true],
+        }
+    }
+}
+
+impl RenderState {
+    
+    pub fn Blended(blendMode : BlendMode) -> RenderState {
+        RenderState { blendMode: blendMode, .. RenderState::default() }
+    }
+}
+
+// Remembers the state last sent to GL, so consecutive commands with the same
+// state don't cause redundant GL calls.
+pub struct RenderStateCache {
+    // None until the first state is applied, then everything is set once
+    current : Option<RenderState>,
+}
+
+impl RenderStateCache {
+
+    pub fn new() -> RenderStateCache {
+        RenderStateCache {
+            current: None,
+        }
+    }
+
+    // Forget the cached state, needed when GL state was changed behind the
+    // cache's back (e.g. by another library).
+    pub fn Invalidate(& mut self) {
+        self.current = None;
+    }
+
+    pub fn Apply(& mut self, state : & RenderState) {
+
+        let current = self.current;
+
+        if current.map_or(true, |c| c.blendMode != state.blendMode) {
+            ApplyBlendMode(state.blendMode);
+            stats::CountStateChange();
+        }
+
+        if current.map_or(true, |c| c.scissor != state.scissor) {
+            match state.scissor {
+                Some(rect) => {
+                    gl::enable(gl::GL_SCISSOR_TEST);
+                    gl::scissor(rect.x, rect.y, rect.width, rect.height);
+                },
+                None => gl::disable(gl::GL_SCISSOR_TEST),
+            }
+            stats::CountStateChange();
+        }
+
+        if current.map_or(true, |c| c.cullMode != state.cullMode) {
+            match state.cullMode {
+                CullMode::None => gl::disable(gl::GL_CULL_FACE),
+                CullMode::Front => {
+                    gl::enable(gl::GL_CULL_FACE);
+                    gl::cull_face(gl::GL_FRONT);
+                },
+                CullMode::Back => {
+                    gl::enable(gl::GL_CULL_FACE);
+                    gl::cull_face(gl::GL_BACK);
+                },
+            }
+            stats::CountStateChange();
+        }
+
+        if current.map_or(true, |c| c.colorMask != state.colorMask) {
+            let mask = state.colorMask;
+            gl::color_mask(mask[0], mask[1], mask[2], mask[3]);
+            stats::CountStateChange();
+        }
+
+        self.current = Some(*state);
+    }
+}
+
+fn ApplyBlendMode(blendMode : BlendMode) {
+    match blendMode {
+        BlendMode::Opaque => gl::disable(gl::GL_BLEND),
+        BlendMode::Alpha => {
+            gl::enable(gl::GL_BLEND);
+            gl::blend_func(gl::GL_SRC_ALPHA, gl::GL_ONE_MINUS_SRC_ALPHA);
+        },
+        BlendMode::Additive => {
+            gl::enable(gl::GL_BLEND);
+            gl::blend_func(gl::GL_SRC_ALPHA, gl::GL_ONE);
+        },
+        BlendMode::Premultiplied => {
+            gl::enable(gl::GL_BLEND);
+            gl::blend_func(gl::GL_ONE, gl::GL_ONE_MINUS_SRC_ALPHA);
+        },
+    }
+}
+
+////////////////////////////////////
+// Vertex Layout
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ComponentType {
+    Byte,
+    UnsignedByte,
+    Short,
+    UnsignedShort,
+    Fixed,
+    Float,
+}
+
+fn ComponentTypeToGL(vit : & ComponentType) -> gl::GLenum {
+    match *vit {
+        ComponentType::Byte => gl::GL_BYTE,
+        ComponentType::UnsignedByte => gl::GL_UNSIGNED_BYTE,
+        ComponentType::Short => gl::GL_SHORT,
+        ComponentType::UnsignedShort => gl::GL_UNSIGNED_SHORT,
+        ComponentType::Fixed => gl::GL_FIXED,
+        ComponentType::Float => gl::GL_FLOAT,
+    }
+}
+
+// size of a single component in bytes
+pub fn ComponentSize(vit : & ComponentType) -> u32 {
+    match *vit {
+        ComponentType::Byte | ComponentType::UnsignedByte => 1,
+        ComponentType::Short | ComponentType::UnsignedShort => 2,
+        ComponentType::Fixed | ComponentType::Float => 4,
+    }
+}
+
+#[derive(Clone)]
+pub struct VertexAttribute {
+    // name of the attribute in the shader
+    pub name : String,
+    pub numComponents : u32,
+    pub componentType : ComponentType,
+    pub normalized : bool,
+    // byte offset inside a single vertex
+    pub offset : u32,
+}
+
+// Describes how the attributes of a vertex are laid out in a single
+// (interleaved) buffer. 
+//
+// let layout = VertexLayout::new()
+//                  .Add("a_vertex", 2, ComponentType::Float, false)
+//                  .Add("a_color", 4, ComponentType::UnsignedByte, true);
+#[derive(Clone)]
+pub struct VertexLayout {
+    pub attributes : Vec<VertexAttribute>,
+    // size of a single vertex in bytes
+    pub stride : u32,
+}
+
+impl VertexLayout {
+
+    pub fn new() -> VertexLayout {
+        VertexLayout {
+            attributes: vec![],
+            stride: 0,
+        }
+    }
+
+    // Appends an attribute behind the previous one. The offset is aligned to
+    // 4 bytes, as the VideoCore fetches unaligned attributes much slower.
+    pub fn Add(self, name : & str, numComponents : u32, componentType : ComponentType, normalized : bool) -> VertexLayout {
+        let offset = (self.stride + 3) & !3;
+        self.AddAt(name, numComponents, componentType, normalized, offset)
+    }
+
+    // Places an attribute at an explicit byte offset inside the vertex.
+    pub fn AddAt(mut self, name : & str, numComponents : u32, componentType : ComponentType, normalized : bool, offset : u32) -> VertexLayout {
+        let end = offset + numComponents * ComponentSize(& componentType);
+        
+        self.attributes.push(VertexAttribute {
+            name: name.to_owned(),
+            numComponents: numComponents,
+            componentType: componentType,
+            normalized: normalized,
+            offset: offset,
+        });
+
+        if end > self.stride {
+            self.stride = (end + 3) & !3;
+        }
+        self
+    }
+
+    // Overrides the computed stride, e.g. when the vertex contains padding
+    // or data that is not consumed by the shader.
+    pub fn WithStride(mut self, stride : u32) -> VertexLayout {
+        self.stride = stride;
+        self
+    }
+
+    // Loads a layout from a file with one attribute per line, in the order
+    // they appear in the vertex. Lines starting with '#' are comments.
+    //
+    // # name      components  type  [normalized]
+    // a_vertex    2           float
+    // a_color     4           ubyte normalized
+    pub fn Load(path : & str) -> Result<VertexLayout> {
+
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => return Err(Error::Io(path.to_owned(), err))
+        };
+
+        let mut layout = VertexLayout::new();
+
+        for line in content.lines() {
+            let l = line.trim();
+
+            if l.starts_with("#") || l.len() == 0 {
+                continue;
+            }
+
+            let invalidLine = || Error::InvalidLayout { 
+                path: path.to_owned(), 
+                reason: format!("Line '{}' is not a valid attribute. 'name components type [normalized]' expected.", line),
+            };
+
+            let tokens : Vec<& str> = l.split_whitespace().collect();
+            if tokens.len() < 3 || tokens.len() > 4 {
+                return Err(invalidLine());
+            }
+
+            let numComponents = match tokens[1].parse::<u32>() {
+                Ok(n) if n >= 1 && n <= 4 => n,
+                _ => return Err(invalidLine()),
+            };
+
+            let componentType = match tokens[2] {
+                "byte" => ComponentType::Byte,
+                "ubyte" => ComponentType::UnsignedByte,
+                "short" => ComponentType::Short,
+                "ushort" => ComponentType::UnsignedShort,
+                "fixed" => ComponentType::Fixed,
+                "float" => ComponentType::Float,
+                _ => return Err(invalidLine()),
+            };
+
+            let normalized = match tokens.get(3) {
+                Some(& "normalized") => true,
+                Some(_) => return Err(invalidLine()),
+                None => false,
+            };
+
+            layout = layout.Add(tokens[0], numComponents, componentType, normalized);
+        }
+
+        Ok(layout)
+    }
+}
+
 ////////////////////////////////////
 // GPUBuffer
 
@@ -157,6 +685,8 @@ pub struct GPUBuffer {
     pub handle : gl::GLuint,
     target : GPUBufferTarget,
     usage : GPUBufferUsage,
+    // size of the allocated storage in bytes
+    size : Cell<usize>,
 }
 
 impl GPUBuffer {
@@ -169,14 +699,130 @@ impl GPUBuffer {
         gl::bind_buffer(glTarget, vbo);
         gl::buffer_data(glTarget, cpuData, UsageToGL(& usage));
 
+        resources::TrackCreate(ResourceKind::Buffer);
+        stats::TrackBufferMemory(cpuData.len() * mem::size_of::<T>(), 0);
+
         GPUBuffer {
             handle : vbo,
             target: target,
             usage: usage,
+            size: Cell::new(cpuData.len() * mem::size_of::<T>()),
+        }
+    }
+
+    // Allocates zeroed storage, which is meant to be filled via Update/UpdateRange.
+    pub fn Allocate(sizeInBytes : usize, target : GPUBufferTarget, usage : GPUBufferUsage) -> GPUBuffer {
+        GPUBuffer::new(& vec![0u8; sizeInBytes], target, usage)
+    }
+
+    pub fn Size(& self) -> usize {
+        self.size.get()
+    }
+
+    // Replaces the whole content of the buffer. The storage is respecified,
+    // which orphans the old one, so the driver does not have to wait for
+    // draws still reading from it. The buffer grows/shrinks to the new data.
+    pub fn Update<T>(& self, cpuData : &[T]) {
+        let glTarget = TargetToGL(& self.target);
+
+        gl::bind_buffer(glTarget, self.handle);
+        gl::buffer_data(glTarget, cpuData, UsageToGL(& self.usage));
+
+        stats::TrackBufferMemory(cpuData.len() * mem::size_of::<T>(), self.Size());
+        self.size.set(cpuData.len() * mem::size_of::<T>());
+    }
+
+    // Overwrites a part of the buffer starting at offset (in bytes). The
+    // storage is not reallocated, writing past its end is rejected.
+    pub fn UpdateRange<T>(& self, offset : usize, cpuData : &[T]) -> Result<()> {
+        let end = offset + cpuData.len() * mem::size_of::<T>();
+
+        if end > self.Size() {
+            return Err(Error::BufferOverflow { requested: end, capacity: self.Size() });
+        }
+
+        let glTarget = TargetToGL(& self.target);
+
+        gl::bind_buffer(glTarget, self.handle);
+        gl::buffer_sub_data(glTarget, offset as gl::GLintptr, cpuData);
+
+        Ok(())
+    }
+
+    // Detaches the current storage from the handle and allocates a fresh one
+    // of the same size. Subsequent UpdateRange calls won't stall on the GPU.
+    pub fn Orphan(& self) {
+        let glTarget = TargetToGL(& self.target);
+
+        gl::bind_buffer(glTarget, self.handle);
+        // without data the new storage is left uninitialized, so nothing is
+        // copied
+        unsafe {
+            glBufferData(glTarget, self.Size() as isize, ptr::null(), UsageToGL(& self.usage));
         }
     }
 }
 
+// the wrapper only takes a slice, orphaning needs a null pointer
+extern "C" {
+    fn glBufferData(target : gl::GLenum, size : isize, data : *const libc::c_void, usage : gl::GLenum);
+}
+
+impl Drop for GPUBuffer {
+    fn drop(& mut self) {
+        gl::delete_buffers(&[self.handle]);
+        resources::TrackRelease(ResourceKind::Buffer);
+        stats::TrackBufferMemory(0, self.Size());
+    }
+}
+
+// Ring buffer for geometry that is generated every frame (text, graphs, ...).
+// Data is appended behind the previous push and the returned byte offset is
+// used as base offset of the command (RenderCommand::SetBaseOffset). Once the end is reached, 
+// the storage is orphaned and writing starts at the beginning again.
+pub struct GPURingBuffer {
+    buffer : Rc<GPUBuffer>,
+    // byte offset of the next write
+    head : usize,
+}
+
+impl GPURingBuffer {
+
+    pub fn new(sizeInBytes : usize, target : GPUBufferTarget) -> GPURingBuffer {
+        GPURingBuffer {
+            buffer: Rc::new(GPUBuffer::Allocate(sizeInBytes, target, GPUBufferUsage::Stream)),
+            head: 0,
+        }
+    }
+
+    // Appends the data and returns the byte offset it was written to.
+    // Data larger than the whole ring is rejected.
+    pub fn Push<T>(& mut self, cpuData : &[T]) -> Result<usize> {
+        let size = cpuData.len() * mem::size_of::<T>();
+
+        if size > self.buffer.Size() {
+            return Err(Error::BufferOverflow { requested: size, capacity: self.buffer.Size() });
+        }
+
+        if self.head + size > self.buffer.Size() {
+            self.buffer.Orphan();
+            self.head = 0;
+        }
+
+        let offset = self.head;
+        self.buffer.UpdateRange(offset, cpuData)?;
+
+        // keep every push 4 byte aligned for the attribute fetch
+        self.head = (offset + size + 3) & !3;
+
+        Ok(offset)
+    }
+
+    pub fn Buffer(& self) -> & Rc<GPUBuffer> {
+        & self.buffer
+    }
+}
+
 
 fn TargetToGL(vit : & GPUBufferTarget) -> gl::GLenum {
     match *vit {
@@ -198,76 +844,295 @@ fn UsageToGL(vit : & GPUBufferUsage) -> gl::GLenum {
 pub struct ShaderProgram(gl::GLuint);
 pub struct ShaderCode(gl::GLuint);
 
+impl ShaderProgram {
+    fn new(hndl : gl::GLuint) -> ShaderProgram {
+        resources::TrackCreate(ResourceKind::Program);
+        ShaderProgram(hndl)
+    }
+}
+
+impl Drop for ShaderProgram {
+    fn drop(& mut self) {
+        gl::delete_program(self.0);
+        resources::TrackRelease(ResourceKind::Program);
+    }
+}
+
+impl ShaderCode {
+    fn new(hndl : gl::GLuint) -> ShaderCode {
+        resources::TrackCreate(ResourceKind::Shader);
+        ShaderCode(hndl)
+    }
+}
+
+impl Drop for ShaderCode {
+    fn drop(& mut self) {
+        gl::delete_shader(self.0);
+        resources::TrackRelease(ResourceKind::Shader);
+    }
+}
+
+
+// An active attribute or uniform of a linked program
+#[derive(Clone, Debug)]
+pub struct ShaderVariable {
+    pub name : String,
+    pub location : gl::GLint,
+    // GL type, e.g. GL_FLOAT_VEC2 or GL_SAMPLER_2D
+    pub glType : gl::GLenum,
+    // number of array elements, 1 for non-arrays
+    pub size : gl::GLint,
+}
+
+// number of components of a GL type, 0 for samplers and matrices
+pub fn NumComponents(glType : gl::GLenum) -> u32 {
+    match glType {
+        gl::GL_FLOAT | gl::GL_INT | gl::GL_BOOL => 1,
+        gl::GL_FLOAT_VEC2 | gl::GL_INT_VEC2 | gl::GL_BOOL_VEC2 => 2,
+        gl::GL_FLOAT_VEC3 | gl::GL_INT_VEC3 | gl::GL_BOOL_VEC3 => 3,
+        gl::GL_FLOAT_VEC4 | gl::GL_INT_VEC4 | gl::GL_BOOL_VEC4 => 4,
+        _ => 0,
+    }
+}
+
+fn IsSampler(glType : gl::GLenum) -> bool {
+    glType == gl::GL_SAMPLER_2D || glType == gl::GL_SAMPLER_CUBE
+}
 
 pub struct ShaderStage {
     program : ShaderProgram,
-    fragShader : ShaderCode,
-    vertShader : ShaderCode,
+    // empty if the program was loaded from a binary
+    shaders : Vec<ShaderCode>,
+    // see cache::ShaderSourceHash
+    sourceHash : u64,
+    // active inputs, queried after linking
+    attributes : Vec<ShaderVariable>,
+    uniforms : Vec<ShaderVariable>,
 }
 
 pub struct ShaderDataHndl(gl::GLuint);
 
 impl ShaderStage {
+
+    // activates the program for the following draws
+    pub fn Use(&self) {
+        gl::use_program(self.program.0);
+        stats::CountStateChange();
+    }
+
+    pub fn Attributes(&self) -> & Vec<ShaderVariable> {
+        & self.attributes
+    }
+
+    pub fn Uniforms(&self) -> & Vec<ShaderVariable> {
+        & self.uniforms
+    }
+
+    pub fn Attribute(&self, name : & str) -> Option<& ShaderVariable> {
+        self.attributes.iter().find(|a| a.name == name)
+    }
+
+    pub fn Uniform(&self, name : & str) -> Option<& ShaderVariable> {
+        self.uniforms.iter().find(|u| u.name == name)
+    }
+
+    pub fn SourceHash(&self) -> u64 {
+        self.sourceHash
+    }
+
+    // Binary format and binary of the linked program, for the asset cache
+    pub fn ProgramBinary(&self, ext : & ProgramBinaryExt) -> Option<(gl::GLenum, Vec<u8>)> {
+        ext.GetBinary(self.program.0)
+    }
     
-    pub fn BindAttribute(&self, attributeName : & str, buffer : & GPUBuffer, componentsPerVertex : u32) -> AttributeBinding {
+    // Binds a tightly packed float attribute stored in its own buffer.
+    pub fn BindAttribute(&self, attributeName : & str, buffer : & Rc<GPUBuffer>, componentsPerVertex : u32) -> Result<AttributeBinding> {
+        
+        Ok(AttributeBinding {
+            attributeHndl: self.AttributeLocation(attributeName)?,
+            dataBuffer: buffer.clone(),
+            numComponents: componentsPerVertex,
+            componentType: ComponentType::Float,
+            normalized: false,
+            stride: 0,
+            offset: 0,
+        })
+    }
+
+    // Binds all attributes of the layout to the interleaved buffer.
+    pub fn BindVertexLayout(&self, layout : & VertexLayout, buffer : & Rc<GPUBuffer>) -> Result<Vec<AttributeBinding>> {
+        self.BindVertexLayoutAt(layout, buffer, 0)
+    }
+
+    // Same as BindVertexLayout, but the vertices start at baseOffset bytes
+    // inside the buffer (e.g. the offset returned by GPURingBuffer::Push).
+    // Layout attributes the shader doesn't use are skipped, so one layout
+    // can serve all variants of a shader. Fails if the layout lacks an
+    // attribute of the shader, use ValidateLayout once for the warnings.
+    pub fn BindVertexLayoutAt(&self, layout : & VertexLayout, buffer : & Rc<GPUBuffer>, baseOffset : usize) -> Result<Vec<AttributeBinding>> {
         
-        let attributeHndl = gl::get_attrib_location(self.program.0, attributeName) as gl::GLuint;
+        if let Some(missing) = self.attributes.iter().find(|attribute| layout.attributes.iter().all(|a| a.name != attribute.name)) {
+            return Err(Error::AttributeNotFound(missing.name.clone()));
+        }
 
-        if attributeHndl == gl::GL_INVALID_OPERATION
-        {
-            panic!("Failed to bind attribute '{}'", attributeName);
+        layout.attributes.iter().filter(|attribute| self.Attribute(& attribute.name).is_some()).map(|attribute| {
+            Ok(AttributeBinding {
+                attributeHndl: self.AttributeLocation(& attribute.name)?,
+                dataBuffer: buffer.clone(),
+                numComponents: attribute.numComponents,
+                componentType: attribute.componentType,
+                normalized: attribute.normalized,
+                stride: layout.stride,
+                offset: baseOffset as u32 + attribute.offset,
+            })
+        }).collect()
+    }
+
+    // Checks that the layout provides every active attribute of the shader,
+    // meant to be called once when the layout is loaded. Attributes that are
+    // unused or have more components than the shader reads are reported as
+    // warning, GL handles both but it's likely a mistake. Fewer components
+    // are fine, GL fills in the rest (e.g. a 2D position for a vec4).
+    pub fn ValidateLayout(&self, layout : & VertexLayout) -> Result<()> {
+
+        for attribute in self.attributes.iter() {
+            match layout.attributes.iter().find(|a| a.name == attribute.name) {
+                Some(provided) => {
+                    let expected = NumComponents(attribute.glType);
+                    if provided.numComponents > expected {
+                        println!("Warning: attribute '{}' has {} components in the layout, but the shader only reads {}", attribute.name, provided.numComponents, expected);
+                    }
+                },
+                None => return Err(Error::AttributeNotFound(attribute.name.clone())),
+            }
         }
 
-        AttributeBinding {
-            attributeHndl: attributeHndl,
-            dataBufferHndl: buffer.handle,
-            numComponents: componentsPerVertex,
+        for provided in layout.attributes.iter() {
+            if self.Attribute(& provided.name).is_none() {
+                println!("Warning: layout attribute '{}' is not used by the shader", provided.name);
+            }
         }
+
+        Ok(())
     }
 
-    pub fn BindUniform(&self, uniformName : & str, uniformData : Vec<i32>) -> UniformBinding {
-        
-        let hndl = gl::get_uniform_location(self.program.0, uniformName);
-        
-        if hndl < 0
-        {
-            panic!("Failed to bind uniform '{}'", uniformName);
-        }
+    // Reports active uniforms and samplers the command doesn't bind. They
+    // keep their last value, which is usually not what was intended.
+    // passUniforms are set by the pass (e.g. u_offset) and are skipped.
+    pub fn CheckBindings(&self, command : & RenderCommand, passUniforms : &[& str]) {
 
-        UniformBinding {
-            handle: hndl,
-            data: UniformTypedData::Integer(uniformData.clone()),
+        for uniform in self.uniforms.iter().filter(|uniform| !passUniforms.contains(& uniform.name.as_str())) {
+            let isBound = command.uniformBindings.iter().any(|b| b.handle == uniform.location) ||
+                          command.textureBindings.iter().any(|b| b.handle == uniform.location);
+
+            if !isBound {
+                println!("Warning: uniform '{}' is not bound", uniform.name);
+            }
         }
     }
-}
 
- pub fn Render(shaderStages : & Vec<ShaderStage>, commands : & Vec<Vec<RenderCommand>>) {
+    fn AttributeLocation(&self, attributeName : & str) -> Result<gl::GLuint> {
         
-    for (i, stage) in shaderStages.iter().enumerate() {
+        match self.Attribute(attributeName) {
+            Some(attribute) if attribute.location >= 0 => Ok(attribute.location as gl::GLuint),
+            _ => Err(Error::AttributeNotFound(attributeName.to_owned())),
+        }
+    }
+
+    // Binds the texture to the sampler uniform. The texture unit is chosen
+    // by the RenderCommand when drawing.
+    pub fn BindTexture(&self, samplerName : & str, texture : & Rc<Texture>) -> Result<TextureBinding> {
         
-        let commands = & commands[i];
+        let uniform = match self.Uniform(samplerName) {
+            Some(uniform) if IsSampler(uniform.glType) => uniform,
+            Some(_) => return Err(Error::UniformTypeMismatch(samplerName.to_owned())),
+            None => return Err(Error::UniformNotFound(samplerName.to_owned())),
+        };
+
+        Ok(TextureBinding {
+            handle: uniform.location,
+            texture: texture.clone(),
+        })
+    }
+
+    pub fn BindUniform(&self, uniformName : & str, uniformData : Vec<i32>) -> Result<UniformBinding> {
         
-        for cmd in commands.iter() {
-            cmd.Execute();
+        let uniform = match self.Uniform(uniformName) {
+            Some(uniform) => uniform,
+            None => return Err(Error::UniformNotFound(uniformName.to_owned())),
+        };
+
+        if uniformData.len() < 1 || uniformData.len() > 4 {
+            return Err(Error::InvalidUniformSize(uniformName.to_owned(), uniformData.len()));
+        }
+
+        // samplers are set as single integers
+        let expected = if IsSampler(uniform.glType) { 1 } else { NumComponents(uniform.glType) as usize };
+        if uniformData.len() != expected {
+            return Err(Error::InvalidUniformSize(uniformName.to_owned(), uniformData.len()));
         }
+
+        Ok(UniformBinding {
+            handle: uniform.location,
+            data: UniformTypedData::Integer(uniformData.clone()),
+        })
     }
 }
 
-impl Default for ShaderStage 
-{
-    fn default() -> ShaderStage {
-        ShaderStage {
-            program: ShaderProgram(0),
-            fragShader: ShaderCode(0),
-            vertShader: ShaderCode(0),
+// Queries the active attributes of a linked program
+fn ReflectAttributes(program : gl::GLuint) -> Vec<ShaderVariable> {
+
+    let count = gl::get_programiv(program, gl::GL_ACTIVE_ATTRIBUTES);
+
+    (0 .. count as gl::GLuint).filter_map(|idx| gl::get_active_attrib(program, idx)).map(|active| {
+        ShaderVariable {
+            location: gl::get_attrib_location(program, & active.name),
+            name: active.name,
+            glType: active.type_,
+            size: active.size,
         }
-    }
+    }).collect()
+}
+
+// Queries the active uniforms of a linked program
+fn ReflectUniforms(program : gl::GLuint) -> Vec<ShaderVariable> {
+
+    let count = gl::get_programiv(program, gl::GL_ACTIVE_UNIFORMS);
+
+    (0 .. count as gl::GLuint).filter_map(|idx| gl::get_active_uniform(program, idx)).map(|active| {
+        // arrays are reported as 'name[0]'
+        let name = match active.name.find('[') {
+            Some(idx) => active.name[.. idx].to_owned(),
+            None => active.name.clone(),
+        };
+
+        ShaderVariable {
+            location: gl::get_uniform_location(program, & name),
+            name: name,
+            glType: active.type_,
+            size: active.size,
+        }
+    }).collect()
 }
 
 // LoadShader loads the shaderfiles located at the specified path.
 // The path must omit the file extension. Then the system will look
 // for '<path>.vert' and '<path>.frag' and load them accordingly.
-pub fn LoadShaderStage(path : & str) -> Result<ShaderStage, ()> {
+pub fn LoadShaderStage(path : & str) -> Result<ShaderStage> {
+    LoadShaderStageVariant(path, &[])
+}
+
+// Same as LoadShaderStage, but the defines are added to both shaders
+// (see preprocessor::PreprocessShader), e.g. &["TEXTURED", "VERTEX_COLOR"].
+pub fn LoadShaderStageVariant(path : & str, defines : &[& str]) -> Result<ShaderStage> {
+    LoadShaderStageCached(path, defines, None)
+}
+
+// Same as LoadShaderStageVariant, but the program is loaded from the binary
+// at cachePath if it was built from the same sources (see
+// cache::ProgramCachePath). A missing, stale or rejected binary falls back
+// to compiling the sources.
+pub fn LoadShaderStageCached(path : & str, defines : &[& str], cachePath : Option<& str>) -> Result<ShaderStage> {
 
     let startTime = Instant::now();
 
@@ -277,61 +1142,225 @@ pub fn LoadShaderStage(path : & str) -> Result<ShaderStage, ()> {
     let mut fragPath = path.to_owned();
     fragPath.push_str(".frag");
 
-    if !Path::new(& vertPath).exists() || !Path::new(& fragPath).exists()
-    {
-        let errString = "Load shader for {} failed. Failed to find vertex/fragment shader.";
-        panic!(errString);
+    for shaderPath in [& vertPath, & fragPath].iter() {
+        if !Path::new(shaderPath).exists() {
+            return Err(Error::FileNotFound(shaderPath.to_string()));
+        }
     }
 
-    let program = gl::create_program();
+    let fragCode = preprocessor::PreprocessShader(& fragPath, defines)?;
+    let vertCode = preprocessor::PreprocessShader(& vertPath, defines)?;
+    let sourceHash = cache::ShaderSourceHash(& vertCode, & fragCode, defines);
+
+    if let Some(cachePath) = cachePath {
+        if let Some(program) = LoadProgramBinary(cachePath, sourceHash) {
+            println!("LoadShaderStage({} {:?}) from {} -- {}ms", path, defines, cachePath, startTime.elapsed().as_millis());
+            return Ok(CreateShaderStage(program, vec![], sourceHash));
+        }
+    }
 
     // setup fragment shader
-    let fragShader = LoadShaderInternal(& fragPath, gl::GL_FRAGMENT_SHADER).unwrap();
-    gl::attach_shader(program, fragShader);
+    let fragShader = LoadShaderInternal(& fragPath, gl::GL_FRAGMENT_SHADER, & fragCode)?;
     // setup vertex shader
-    let vertShader = LoadShaderInternal(& vertPath, gl::GL_VERTEX_SHADER).unwrap();
-    gl::attach_shader(program, vertShader);
+    let vertShader = LoadShaderInternal(& vertPath, gl::GL_VERTEX_SHADER, & vertCode)?;
 
-    gl::link_program(program);
+    let program = ShaderProgram::new(gl::create_program());
+    gl::attach_shader(program.0, fragShader.0);
+    gl::attach_shader(program.0, vertShader.0);
 
-    if gl::get_programiv(program, gl::GL_LINK_STATUS) == gl::GL_FALSE as i32 {
-        match gl::get_program_info_log(program, 1024) {
-            Some(log) => println!("Failed to link shaders: {}\n{}",path, log),
-            None => ()
-        }
+    gl::link_program(program.0);
+
+    if gl::get_programiv(program.0, gl::GL_LINK_STATUS) == gl::GL_FALSE as i32 {
+        let log = gl::get_program_info_log(program.0, 1024).unwrap_or(String::new());
+        return Err(Error::ShaderLink { path: path.to_owned(), log: log });
+    }
+
+    println!("LoadShaderStage({} {:?}) -- {}ms", path, defines, startTime.elapsed().as_millis());
+    Ok(CreateShaderStage(program, vec![fragShader, vertShader], sourceHash))
+}
+
+fn CreateShaderStage(program : ShaderProgram, shaders : Vec<ShaderCode>, sourceHash : u64) -> ShaderStage {
+    let attributes = ReflectAttributes(program.0);
+    let uniforms = ReflectUniforms(program.0);
+
+    ShaderStage{
+        program: program,
+        shaders: shaders,
+        sourceHash: sourceHash,
+        attributes: attributes,
+        uniforms: uniforms,
     }
+}
+
+// None if there is no up to date binary or the driver rejected it
+fn LoadProgramBinary(cachePath : & str, sourceHash : u64) -> Option<ShaderProgram> {
+
+    let (format, binary) = cache::ReadProgramCache(cachePath, sourceHash)?;
+    let ext = ProgramBinaryExt::Load()?;
 
-    gl::use_program(program);
+    let program = ShaderProgram::new(gl::create_program());
+    if !ext.LoadBinary(program.0, format, & binary) {
+        println!("Ignoring program binary {} rejected by the driver", cachePath);
+        return None;
+    }
 
-    println!("LoadShaderStage({}) -- {}ms", path, startTime.elapsed().as_millis());
-    Ok(ShaderStage{
-        program: ShaderProgram(program),
-        fragShader: ShaderCode(fragShader),
-        vertShader: ShaderCode(vertShader),
-    })
+    Some(program)
 }
 
-fn LoadShaderInternal(path : & str, shaderType : gl::GLenum) -> Result<gl::GLuint, ()>
+fn LoadShaderInternal(path : & str, shaderType : gl::GLenum, shaderCode : & str) -> Result<ShaderCode>
 {
-    let shaderCode = match fs::read_to_string(path) {
-        Ok(content) => content,
-        Err(error) => {
-            panic!("Failed to load shader: {}", error);
+    let shader = ShaderCode::new(gl::create_shader(shaderType));
+    println!("LoadShader({}) -> {}", path, shader.0);
+
+    gl::shader_source(shader.0, shaderCode.as_bytes());
+    gl::compile_shader(shader.0);
+
+    if gl::get_shaderiv(shader.0, gl::GL_COMPILE_STATUS) == gl::GL_FALSE as i32 {
+        let log = gl::get_shader_info_log(shader.0, 1024).unwrap_or(String::new());
+        return Err(Error::ShaderCompile { path: path.to_owned(), log: AnnotateShaderLog(& log, shaderCode) });
+    }
+
+    Ok(shader)
+}
+
+// Appends the offending source line below each message of the info log.
+fn AnnotateShaderLog(log : & str, source : & str) -> String {
+    
+    let sourceLines : Vec<& str> = source.lines().collect();
+    let mut annotated = String::new();
+
+    for line in log.lines() {
+        annotated.push_str(line);
+        annotated.push('\n');
+
+        if let Some(lineNr) = ParseLogLineNumber(line) {
+            if lineNr >= 1 && lineNr <= sourceLines.len() {
+                annotated.push_str(& format!("    {:>4} | {}\n", lineNr, sourceLines[lineNr - 1]));
+            }
+        }
+    }
+
+    annotated
+}
+
+// Extracts the source line a compiler message refers to. Depending on the
+// driver, messages look like "0:12: ...", "0:12(5): ..." or "ERROR: 0:12: ...".
+// Only the "<source>:<line>" prefix is parsed, numbers in the message text
+// are ignored.
+fn ParseLogLineNumber(line : & str) -> Option<usize> {
+
+    let mut rest = line.trim_start();
+    for severity in [ "ERROR:", "WARNING:" ].iter() {
+        if rest.starts_with(severity) {
+            rest = rest[severity.len()..].trim_start();
+            break;
         }
+    }
+
+    let sourceEnd = rest.find(|c : char| !c.is_digit(10))?;
+    if sourceEnd == 0 || !rest[sourceEnd..].starts_with(':') {
+        return None;
+    }
+
+    let rest = & rest[sourceEnd + 1..];
+    let lineEnd = rest.find(|c : char| !c.is_digit(10))?;
+    if lineEnd == 0 || !(rest[lineEnd..].starts_with(':') || rest[lineEnd..].starts_with('(')) {
+        return None;
+    }
+
+    rest[..lineEnd].parse::<usize>().ok()
+}
+
+// File of the data directory listing the shader variants in use
+pub const SHADER_VARIANTS_FILE : & str = "shader.variants";
+
+// A shader and the defines it's compiled with (see LoadShaderStageVariant)
+#[derive(Clone, Debug)]
+pub struct ShaderVariant {
+    pub name : String,
+    pub defines : Vec<String>,
+}
+
+// Loads a list of shader variants with one variant per line, the shader
+// name followed by its defines. Lines starting with '#' are comments.
+//
+// # shader  defines
+// default   TEXTURED VERTEX_COLOR
+pub fn LoadShaderVariants(path : & str) -> Result<Vec<ShaderVariant>> {
+
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) => return Err(Error::Io(path.to_owned(), err))
     };
+
+    Ok(content.lines().map(|line| line.trim()).filter(|l| !l.starts_with("#") && l.len() > 0).map(|l| {
+        let mut tokens = l.split_whitespace().map(|token| token.to_owned());
+        ShaderVariant {
+            name: tokens.next().unwrap(),
+            defines: tokens.collect(),
+        }
+    }).collect())
+}
+
+// Compiles and links every '<name>.vert'/'<name>.frag' pair in the
+// directory, and every variant listed in its SHADER_VARIANTS_FILE. Meant to
+// be run before deployment, so broken shaders are caught on the bench and
+// not on the road. Returns the result per shader and variant.
+pub fn ValidateShaderDirectory(dirPath : & str) -> Result<Vec<(String, Result<()>)>> {
+
+    Ok(ListShaderVariants(dirPath)?.into_iter().map(|variant| {
+        let path = format!("{}/{}", dirPath, variant.name);
+        let defines : Vec<& str> = variant.defines.iter().map(|define| define.as_str()).collect();
+
+        let result = LoadShaderStageVariant(& path, & defines).map(|_| ());
+
+        if defines.is_empty() {
+            (path, result)
+        } else {
+            (format!("{} {:?}", path, defines), result)
+        }
+    }).collect())
+}
+
+// Every shader of the directory without defines, followed by the variants
+// listed in its SHADER_VARIANTS_FILE. The defines are sorted.
+pub fn ListShaderVariants(dirPath : & str) -> Result<Vec<ShaderVariant>> {
     
-    let shader = gl::create_shader(shaderType);
-    println!("LoadShader({}) -> {}", path, shader);
+    let entries = match fs::read_dir(dirPath) {
+        Ok(entries) => entries,
+        Err(err) => return Err(Error::Io(dirPath.to_owned(), err))
+    };
+
+    let mut shaderNames = vec![];
 
-    gl::shader_source(shader, shaderCode.as_bytes());
-    gl::compile_shader(shader);
+    for entry in entries {
+        let path = match entry {
+            Ok(entry) => entry.path(),
+            Err(err) => return Err(Error::Io(dirPath.to_owned(), err))
+        };
 
-    if gl::get_shaderiv(shader, gl::GL_COMPILE_STATUS) == gl::GL_FALSE as i32 {
-        match gl::get_shader_info_log(shader, 1024) {
-            Some(log) => println!("Compilation Errors:\n{}",log),
-            None => ()
+        let isShader = path.extension().map_or(false, |ext| ext == "vert" || ext == "frag");
+
+        if isShader {
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            if !shaderNames.contains(& name) {
+                shaderNames.push(name);
+            }
         }
     }
 
-    Ok(shader)
+    shaderNames.sort();
+
+    let variantsPath = format!("{}/{}", dirPath, SHADER_VARIANTS_FILE);
+    let listed = if Path::new(& variantsPath).exists() { LoadShaderVariants(& variantsPath)? } else { vec![] };
+
+    let mut variants : Vec<ShaderVariant> = shaderNames.into_iter().map(|name| ShaderVariant { name: name, defines: vec![] }).collect();
+
+    // the variants without defines are the shaders themselves
+    for mut variant in listed.into_iter().filter(|variant| !variant.defines.is_empty()) {
+        variant.defines.sort();
+        variants.push(variant);
+    }
+
+    Ok(variants)
 }
\ No newline at end of file
@@ -0,0 +1,58 @@
+#![allow(non_snake_case)]
+
+// Leak tracking for GPU objects. Every GL object wrapper reports its creation
+// and release here. In debug builds the number of live objects per kind is
+// counted, in release builds all functions compile down to nothing.
+
+#[cfg(debug_assertions)]
+use std::sync::atomic::{AtomicIsize, Ordering};
+
+#[derive(Clone, Copy, Debug)]
+pub enum ResourceKind {
+    Buffer,
+    Texture,
+    Shader,
+    Program,
+}
+
+const ALL_KINDS : [ResourceKind; 4] = [ ResourceKind::Buffer,
+                                        ResourceKind::Texture,
+                                        ResourceKind::Shader,
+                                        ResourceKind::Program ];
+
+#[cfg(debug_assertions)]
+static LIVE_RESOURCES : [AtomicIsize; 4] = [ AtomicIsize::new(0),
+                                             AtomicIsize::new(0),
+                                             AtomicIsize::new(0),
+                                             AtomicIsize::new(0) ];
+
+pub fn TrackCreate(_kind : ResourceKind) {
+    #[cfg(debug_assertions)]
+    LIVE_RESOURCES[_kind as usize].fetch_add(1, Ordering::SeqCst);
+}
+
+pub fn TrackRelease(_kind : ResourceKind) {
+    #[cfg(debug_assertions)]
+    LIVE_RESOURCES[_kind as usize].fetch_sub(1, Ordering::SeqCst);
+}
+
+// Number of objects of the given kind that are currently alive.
+// Always 0 in release builds.
+pub fn LiveCount(_kind : ResourceKind) -> isize {
+    #[cfg(debug_assertions)]
+    return LIVE_RESOURCES[_kind as usize].load(Ordering::SeqCst);
+
+    #[cfg(not(debug_assertions))]
+    0
+}
+
+// Prints all object kinds that still have live instances. Meant to be
+// called right before the GL context is destroyed.
+pub fn ReportLeaks() {
+    for kind in ALL_KINDS.iter() {
+        let count = LiveCount(*kind);
+        if count != 0 {
+            println!("Leaked GPU resources: {} x {:?}", count, kind);
+        }
+    }
+}
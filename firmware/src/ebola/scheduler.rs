@@ -0,0 +1,94 @@
+#![allow(non_snake_case)]
+
+use std::thread;
+use std::time::{ Duration, Instant };
+
+// Paces the main loop to a target frame rate and decides whether a frame
+// has to be rendered at all. The dashboard mostly shows values that change
+// a few times per second, so frames are only rendered when a redraw was
+// requested, which keeps CPU/GPU load and heat in the enclosure down.
+pub struct FrameScheduler {
+    // None renders as fast as possible
+    targetFrameTime : Option<Duration>,
+    // render at least this often, even if nothing changed
+    maxIdleTime : Duration,
+    redrawRequested : bool,
+    frameStart : Instant,
+    lastRender : Instant,
+}
+
+impl FrameScheduler {
+
+    // A target of 0 fps disables the frame rate limit.
+    pub fn new(targetFps : u32) -> FrameScheduler {
+        let now = Instant::now();
+
+        let mut scheduler = FrameScheduler {
+            targetFrameTime: None,
+            maxIdleTime: Duration::from_secs(1),
+            // the first frame is always rendered
+            redrawRequested: true,
+            frameStart: now,
+            lastRender: now,
+        };
+
+        scheduler.SetTargetFps(targetFps);
+        scheduler
+    }
+
+    pub fn SetTargetFps(& mut self, targetFps : u32) {
+        self.targetFrameTime = match targetFps {
+            0 => None,
+            fps => Some(Duration::from_micros(1_000_000 / fps as u64)),
+        };
+    }
+
+    pub fn SetMaxIdleTime(& mut self, maxIdleTime : Duration) {
+        self.maxIdleTime = maxIdleTime;
+    }
+
+    // Marks the frame as dirty, the next frame will be rendered.
+    pub fn RequestRedraw(& mut self) {
+        self.redrawRequested = true;
+    }
+
+    // Stores the new value and requests a redraw if it differs from the
+    // displayed one. Returns whether the value changed.
+    pub fn Track<T : PartialEq>(& mut self, displayed : & mut T, value : T) -> bool {
+        if *displayed == value {
+            return false;
+        }
+
+        *displayed = value;
+        self.RequestRedraw();
+        true
+    }
+
+    // Sleeps until the next frame is due and returns the time that passed
+    // since the start of the previous frame.
+    pub fn BeginFrame(& mut self) -> Duration {
+
+        if let Some(frameTime) = self.targetFrameTime {
+            let elapsed = self.frameStart.elapsed();
+            if elapsed < frameTime {
+                thread::sleep(frameTime - elapsed);
+            }
+        }
+
+        let now = Instant::now();
+        let deltaTime = now - self.frameStart;
+        self.frameStart = now;
+
+        deltaTime
+    }
+
+    pub fn ShouldRender(& self) -> bool {
+        self.redrawRequested || self.lastRender.elapsed() >= self.maxIdleTime
+    }
+
+    // Call after the frame was rendered and swapped.
+    pub fn FrameRendered(& mut self) {
+        self.redrawRequested = false;
+        self.lastRender = Instant::now();
+    }
+}
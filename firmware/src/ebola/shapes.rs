@@ -0,0 +1,560 @@
+#![allow(non_snake_case)]
+
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+use error::Result;
+use renderer::{ BlendMode,
+                ComponentType,
+                GPUBuffer,
+                GPUBufferTarget,
+                GPUBufferUsage,
+                PrimitivesType,
+                RenderCommand,
+                RenderState,
+                ShaderStage,
+                VertexLayout };
+use theme::{ Color, ToColorBytes };
+
+// Tessellates vector shapes into triangles. Every outline gets a fringe of
+// 'feather' pixels whose 'a_edge' attribute falls from 1 to 0, the shader
+// multiplies the alpha with it (default shader with EDGE_AA), so edges are
+// smooth without multisampling. Coordinates are in pixels.
+
+pub type Point = [f32; 2];
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ShapeVertex {
+    pub pos : [f32; 2],
+    pub color : [u8; 4],
+    // coverage, 1 inside the shape and 0 at the outer end of the fringe
+    pub edge : f32,
+}
+
+// Layout of ShapeVertex, matches the attribute names of the default shader
+pub fn ShapeLayout() -> VertexLayout {
+    VertexLayout::new()
+        .Add("a_vertex", 2, ComponentType::Float, false)
+        .Add("a_color", 4, ComponentType::UnsignedByte, true)
+        .Add("a_edge", 1, ComponentType::Float, false)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LineJoin {
+    // pointed corners, sharp ones fall back to Bevel
+    Miter,
+    Bevel,
+    Round,
+}
+
+// corners longer than this times the half thickness are beveled
+const MITER_LIMIT : f32 = 4.0;
+
+// maximum distance in pixels between a curve and its segments
+const CURVE_TOLERANCE : f32 = 0.25;
+
+const EPSILON : f32 = 0.0001;
+
+// Geometry of tessellated shapes, drawn as a triangle list
+pub struct Shape {
+    pub vertices : Rc<GPUBuffer>,
+    pub numVertices : u32,
+}
+
+impl Shape {
+
+    // Draws the shape alpha blended, the shader needs the attributes of
+    // ShapeLayout.
+    pub fn Command(& self, shader : & Rc<ShaderStage>) -> Result<RenderCommand> {
+        let attribs = shader.BindVertexLayout(& ShapeLayout(), & self.vertices)?;
+
+        Ok(RenderCommand::new(attribs, vec![], PrimitivesType::Triangles, self.numVertices)
+                .WithRenderState(RenderState::Blended(BlendMode::Alpha)))
+    }
+
+    // Replaces the geometry, e.g. to move a needle. Commands created before
+    // keep their vertex count, so the new shape has to be built the same way.
+    pub fn Update(& mut self, builder : & ShapeBuilder) {
+        self.vertices.Update(& builder.vertices);
+        self.numVertices = builder.vertices.len() as u32;
+    }
+}
+
+// One cross section of a stroke. Consecutive stations are connected by a
+// quad, plus a fringe quad on either side.
+#[derive(Clone, Copy)]
+struct Station {
+    left : Point,
+    right : Point,
+    // direction of the fringe on either side
+    leftNormal : Point,
+    rightNormal : Point,
+}
+
+// Collects the triangles of any number of shapes, which are then uploaded
+// as one buffer and drawn with one call.
+pub struct ShapeBuilder {
+    vertices : Vec<ShapeVertex>,
+    feather : f32,
+}
+
+impl ShapeBuilder {
+
+    pub fn new() -> ShapeBuilder {
+        ShapeBuilder {
+            vertices: vec![],
+            feather: 1.0,
+        }
+    }
+
+    // Width of the anti-aliased fringe in pixels, 0 gives hard edges.
+    pub fn WithFeather(mut self, feather : f32) -> ShapeBuilder {
+        self.feather = feather;
+        self
+    }
+
+    pub fn Vertices(& self) -> &[ShapeVertex] {
+        & self.vertices
+    }
+
+    pub fn Clear(& mut self) {
+        self.vertices.clear();
+    }
+
+    pub fn Build(& self, usage : GPUBufferUsage) -> Shape {
+        Shape {
+            vertices: Rc::new(GPUBuffer::new(& self.vertices, GPUBufferTarget::Array, usage)),
+            numVertices: self.vertices.len() as u32,
+        }
+    }
+
+    ////////////////////////////////////
+    // Strokes
+
+    // Ring segment around center, e.g. the scale of a dial. Angles are in
+    // radians, clockwise on screen starting at 3 o'clock.
+    pub fn Arc(& mut self, center : Point, radius : f32, thickness : f32, startAngle : f32, endAngle : f32, color : Color) {
+
+        let half = thickness * 0.5;
+        let sweep = endAngle - startAngle;
+        let steps = ArcSegments(radius + half, sweep.abs());
+
+        let stations : Vec<Station> = (0 ..= steps).map(|i| {
+            let angle = startAngle + sweep * i as f32 / steps as f32;
+            let dir = [ angle.cos(), angle.sin() ];
+            Station {
+                left: Add(center, Scale(dir, radius + half)),
+                right: Add(center, Scale(dir, (radius - half).max(0.0))),
+                leftNormal: dir,
+                rightNormal: Scale(dir, -1.0),
+            }
+        }).collect();
+
+        let color = ToColorBytes(color);
+
+        if sweep.abs() >= 2.0 * PI - EPSILON {
+            // the last station is the first one again
+            self.Ribbon(& stations[.. steps], true, color);
+        } else {
+            self.Ribbon(& stations, false, color);
+
+            let startTangent = [ startAngle.sin(), -startAngle.cos() ];
+            let endTangent = [ -endAngle.sin(), endAngle.cos() ];
+            let (startTangent, endTangent) = if sweep < 0.0 { (Scale(startTangent, -1.0), Scale(endTangent, -1.0)) } else { (startTangent, endTangent) };
+
+            self.Cap(& stations[0], startTangent, color);
+            self.Cap(& stations[stations.len() - 1], endTangent, color);
+        }
+    }
+
+    pub fn Ring(& mut self, center : Point, radius : f32, thickness : f32, color : Color) {
+        self.Arc(center, radius, thickness, 0.0, 2.0 * PI, color);
+    }
+
+    // Line through the points with butt caps. Closed polylines connect the
+    // last to the first point with a join instead of caps.
+    pub fn Polyline(& mut self, points : &[Point], thickness : f32, join : LineJoin, closed : bool, color : Color) {
+
+        let points = Dedup(points, closed);
+        if points.len() < 2 {
+            return;
+        }
+
+        let half = thickness * 0.5;
+        let count = points.len();
+        let numSegments = if closed { count } else { count - 1 };
+        let direction = |i : usize| Normalize(Sub(points[(i + 1) % count], points[i]));
+
+        let mut stations = vec![];
+
+        for i in 0 .. count {
+            let hasPrev = closed || i > 0;
+            let hasNext = closed || i + 1 < count;
+
+            if hasPrev && hasNext {
+                let d0 = direction((i + count - 1) % count);
+                let d1 = direction(i);
+                JoinStations(& mut stations, points[i], d0, d1, half, join);
+            } else {
+                let d = if hasNext { direction(i) } else { direction(numSegments - 1) };
+                let n = LeftNormal(d);
+                stations.push(Station {
+                    left: Add(points[i], Scale(n, half)),
+                    right: Sub(points[i], Scale(n, half)),
+                    leftNormal: n,
+                    rightNormal: Scale(n, -1.0),
+                });
+            }
+        }
+
+        let color = ToColorBytes(color);
+        self.Ribbon(& stations, closed, color);
+
+        if !closed {
+            let first = stations[0];
+            let last = stations[stations.len() - 1];
+            self.Cap(& first, Scale(direction(0), -1.0), color);
+            self.Cap(& last, direction(numSegments - 1), color);
+        }
+    }
+
+    ////////////////////////////////////
+    // Fills
+
+    pub fn Circle(& mut self, center : Point, radius : f32, color : Color) {
+        let points = ArcPoints(center, radius, 0.0, 2.0 * PI, false);
+        self.ConvexPolygon(& points, color);
+    }
+
+    // Filled rectangle with corners rounded by radius, which is limited to
+    // half the shorter side.
+    pub fn RoundedRect(& mut self, pos : Point, size : Point, radius : f32, color : Color) {
+        let points = RoundedRectPoints(pos, size, radius);
+        self.ConvexPolygon(& points, color);
+    }
+
+    // Outline of a rounded rectangle, centered on its border
+    pub fn StrokeRoundedRect(& mut self, pos : Point, size : Point, radius : f32, thickness : f32, color : Color) {
+        let points = RoundedRectPoints(pos, size, radius);
+        self.Polyline(& points, thickness, LineJoin::Miter, true, color);
+    }
+
+    // Faster than Polygon, but only correct for convex outlines
+    pub fn ConvexPolygon(& mut self, points : &[Point], color : Color) {
+
+        let points = Dedup(points, true);
+        if points.len() < 3 {
+            return;
+        }
+
+        let color = ToColorBytes(color);
+        for i in 1 .. points.len() - 1 {
+            self.Triangle([ (points[0], 1.0), (points[i], 1.0), (points[i + 1], 1.0) ], color);
+        }
+
+        self.OutlineFringe(& points, color);
+    }
+
+    // Any simple (not self intersecting) polygon, concave ones included.
+    // Triangulated by ear clipping.
+    pub fn Polygon(& mut self, points : &[Point], color : Color) {
+
+        let points = Dedup(points, true);
+        if points.len() < 3 {
+            return;
+        }
+
+        let color = ToColorBytes(color);
+        let ccw = SignedArea(& points) > 0.0;
+        let mut remaining : Vec<usize> = (0 .. points.len()).collect();
+
+        while remaining.len() > 3 {
+            let count = remaining.len();
+            let ear = (0 .. count).find(|& i| {
+                let a = points[remaining[(i + count - 1) % count]];
+                let b = points[remaining[i]];
+                let c = points[remaining[(i + 1) % count]];
+
+                // a convex corner without other points inside
+                let convex = (Cross(Sub(b, a), Sub(c, b)) > 0.0) == ccw;
+                convex && remaining.iter().all(|& j| {
+                    let p = points[j];
+                    p == a || p == b || p == c || !InTriangle(p, a, b, c)
+                })
+            });
+
+            // only happens for degenerate input, emit the rest as a fan
+            let i = match ear {
+                Some(i) => i,
+                None => break,
+            };
+
+            let a = points[remaining[(i + count - 1) % count]];
+            let b = points[remaining[i]];
+            let c = points[remaining[(i + 1) % count]];
+            self.Triangle([ (a, 1.0), (b, 1.0), (c, 1.0) ], color);
+            remaining.remove(i);
+        }
+
+        for i in 1 .. remaining.len() - 1 {
+            self.Triangle([ (points[remaining[0]], 1.0), (points[remaining[i]], 1.0), (points[remaining[i + 1]], 1.0) ], color);
+        }
+
+        self.OutlineFringe(& points, color);
+    }
+
+    ////////////////////////////////////
+    // Tessellation helpers
+
+    fn Vertex(& mut self, pos : Point, edge : f32, color : [u8; 4]) {
+        self.vertices.push(ShapeVertex { pos: pos, color: color, edge: edge });
+    }
+
+    fn Triangle(& mut self, corners : [(Point, f32); 3], color : [u8; 4]) {
+        for & (pos, edge) in corners.iter() {
+            self.Vertex(pos, edge, color);
+        }
+    }
+
+    // two triangles, corners in order around the quad
+    fn Quad(& mut self, corners : [(Point, f32); 4], color : [u8; 4]) {
+        self.Triangle([ corners[0], corners[1], corners[2] ], color);
+        self.Triangle([ corners[0], corners[2], corners[3] ], color);
+    }
+
+    fn Ribbon(& mut self, stations : &[Station], closed : bool, color : [u8; 4]) {
+
+        let count = stations.len();
+        let numQuads = if closed { count } else { count - 1 };
+        let feather = self.feather;
+
+        for i in 0 .. numQuads {
+            let a = stations[i];
+            let b = stations[(i + 1) % count];
+
+            self.Quad([ (a.left, 1.0), (a.right, 1.0), (b.right, 1.0), (b.left, 1.0) ], color);
+
+            if feather > 0.0 {
+                self.Quad([ (Add(a.left, Scale(a.leftNormal, feather)), 0.0), (a.left, 1.0),
+                            (b.left, 1.0), (Add(b.left, Scale(b.leftNormal, feather)), 0.0) ], color);
+                self.Quad([ (a.right, 1.0), (Add(a.right, Scale(a.rightNormal, feather)), 0.0),
+                            (Add(b.right, Scale(b.rightNormal, feather)), 0.0), (b.right, 1.0) ], color);
+            }
+        }
+    }
+
+    // fringe across the open end of a stroke
+    fn Cap(& mut self, station : & Station, outward : Point, color : [u8; 4]) {
+        if self.feather <= 0.0 {
+            return;
+        }
+
+        let offset = Scale(outward, self.feather);
+        self.Quad([ (station.left, 1.0), (station.right, 1.0),
+                    (Add(station.right, offset), 0.0), (Add(station.left, offset), 0.0) ], color);
+    }
+
+    // fringe around a filled outline, pointing away from the inside
+    fn OutlineFringe(& mut self, points : &[Point], color : [u8; 4]) {
+        if self.feather <= 0.0 {
+            return;
+        }
+
+        let count = points.len();
+        // the left normal points inside for counter clockwise outlines
+        let sign = if SignedArea(points) > 0.0 { -1.0 } else { 1.0 };
+        let outward = |i : usize| Scale(LeftNormal(Normalize(Sub(points[(i + 1) % count], points[i]))), sign);
+
+        let offsets : Vec<Point> = (0 .. count).map(|i| {
+            let n0 = outward((i + count - 1) % count);
+            let n1 = outward(i);
+            Scale(MiterDirection(n0, n1), self.feather)
+        }).collect();
+
+        for i in 0 .. count {
+            let j = (i + 1) % count;
+            self.Quad([ (points[i], 1.0), (Add(points[i], offsets[i]), 0.0),
+                        (Add(points[j], offsets[j]), 0.0), (points[j], 1.0) ], color);
+        }
+    }
+}
+
+// Adds the stations of the join at p between the segments with the
+// directions d0 and d1.
+fn JoinStations(stations : & mut Vec<Station>, p : Point, d0 : Point, d1 : Point, half : f32, join : LineJoin) {
+
+    let n0 = LeftNormal(d0);
+    let n1 = LeftNormal(d1);
+    let turn = Cross(d0, d1);
+
+    // the line turns back on itself and has no miter point. The stations
+    // turn half way around p through d0, which caps the end: a bevel through
+    // the tip, or a half circle for round joins.
+    if Length(Add(n0, n1)) < EPSILON {
+        let steps = if join == LineJoin::Round { ArcSegments(half, PI) } else { 2 };
+        let rotation = if Cross(n0, d0) > 0.0 { PI } else { -PI };
+
+        for i in 0 ..= steps {
+            let dir = Rotate(n0, rotation * i as f32 / steps as f32);
+            stations.push(Station {
+                left: Add(p, Scale(dir, half)),
+                right: Sub(p, Scale(dir, half)),
+                leftNormal: dir,
+                rightNormal: Scale(dir, -1.0),
+            });
+        }
+        return;
+    }
+
+    // unit vector towards the left miter point and the distance to it
+    let miterDir = Normalize(Add(n0, n1));
+    let miterLength = 1.0 / Dot(miterDir, n0).max(EPSILON);
+
+    // straight or a miter within the limit, one station is enough
+    if turn.abs() < EPSILON && Dot(d0, d1) > 0.0 || join == LineJoin::Miter && miterLength <= MITER_LIMIT {
+        let offset = Scale(miterDir, half * miterLength);
+        stations.push(Station {
+            left: Add(p, offset),
+            right: Sub(p, offset),
+            leftNormal: miterDir,
+            rightNormal: Scale(miterDir, -1.0),
+        });
+        return;
+    }
+
+    // the inner side keeps the (limited) miter point, the outer side goes
+    // around the corner
+    let inner = Scale(miterDir, half * miterLength.min(MITER_LIMIT));
+    let outerSign = if turn > 0.0 { -1.0 } else { 1.0 };
+    let from = Scale(n0, outerSign);
+    let to = Scale(n1, outerSign);
+
+    let mut outer = vec![from];
+    if join == LineJoin::Round {
+        let angle = Dot(from, to).max(-1.0).min(1.0).acos();
+        let steps = ArcSegments(half, angle);
+        let rotation = if Cross(from, to) > 0.0 { angle } else { -angle };
+        for i in 1 .. steps {
+            outer.push(Rotate(from, rotation * i as f32 / steps as f32));
+        }
+    }
+    outer.push(to);
+
+    for dir in outer {
+        let outerPoint = Add(p, Scale(dir, half));
+
+        stations.push(if turn > 0.0 {
+            Station { left: Add(p, inner), right: outerPoint, leftNormal: miterDir, rightNormal: dir }
+        } else {
+            Station { left: outerPoint, right: Sub(p, inner), leftNormal: dir, rightNormal: Scale(miterDir, -1.0) }
+        });
+    }
+}
+
+// Number of segments for an arc, so it deviates less than CURVE_TOLERANCE
+// from the real curve.
+fn ArcSegments(radius : f32, angle : f32) -> usize {
+    if radius <= CURVE_TOLERANCE {
+        return 1;
+    }
+
+    let maxStep = 2.0 * (1.0 - CURVE_TOLERANCE / radius).acos();
+    ((angle / maxStep).ceil() as usize).max(1).min(256)
+}
+
+fn ArcPoints(center : Point, radius : f32, startAngle : f32, endAngle : f32, includeEnd : bool) -> Vec<Point> {
+    let sweep = endAngle - startAngle;
+    let steps = ArcSegments(radius, sweep.abs());
+    let count = if includeEnd { steps + 1 } else { steps };
+
+    (0 .. count).map(|i| {
+        let angle = startAngle + sweep * i as f32 / steps as f32;
+        [ center[0] + angle.cos() * radius, center[1] + angle.sin() * radius ]
+    }).collect()
+}
+
+// Outline of a rounded rectangle, clockwise on screen
+pub fn RoundedRectPoints(pos : Point, size : Point, radius : f32) -> Vec<Point> {
+
+    let radius = radius.max(0.0).min(size[0].min(size[1]) * 0.5);
+    if radius < EPSILON {
+        return vec![ pos, [pos[0] + size[0], pos[1]], [pos[0] + size[0], pos[1] + size[1]], [pos[0], pos[1] + size[1]] ];
+    }
+
+    let left = pos[0] + radius;
+    let right = pos[0] + size[0] - radius;
+    let top = pos[1] + radius;
+    let bottom = pos[1] + size[1] - radius;
+
+    let mut points = vec![];
+    points.extend(ArcPoints([left, top], radius, PI, 1.5 * PI, true));
+    points.extend(ArcPoints([right, top], radius, 1.5 * PI, 2.0 * PI, true));
+    points.extend(ArcPoints([right, bottom], radius, 0.0, 0.5 * PI, true));
+    points.extend(ArcPoints([left, bottom], radius, 0.5 * PI, PI, true));
+    points
+}
+
+// removes repeated points, which have no direction
+fn Dedup(points : &[Point], closed : bool) -> Vec<Point> {
+    let mut result : Vec<Point> = vec![];
+    for & p in points {
+        if result.last().map_or(true, |& last| Length(Sub(p, last)) > EPSILON) {
+            result.push(p);
+        }
+    }
+
+    if closed && result.len() > 1 && Length(Sub(result[0], result[result.len() - 1])) <= EPSILON {
+        result.pop();
+    }
+
+    result
+}
+
+// Offset of the miter point of a corner between two edges with the unit
+// normals n0 and n1, for lines at distance 1. Limited to MITER_LIMIT.
+fn MiterDirection(n0 : Point, n1 : Point) -> Point {
+    let sum = Add(n0, n1);
+    let length = Length(sum);
+    if length < EPSILON {
+        // the outline turns back on itself, a miter would be a spike
+        return n0;
+    }
+
+    let dir = Scale(sum, 1.0 / length);
+    let cos = Dot(dir, n0).max(1.0 / MITER_LIMIT);
+    Scale(dir, 1.0 / cos)
+}
+
+fn SignedArea(points : &[Point]) -> f32 {
+    let count = points.len();
+    (0 .. count).map(|i| Cross(points[i], points[(i + 1) % count])).sum::<f32>() * 0.5
+}
+
+fn InTriangle(p : Point, a : Point, b : Point, c : Point) -> bool {
+    let d0 = Cross(Sub(b, a), Sub(p, a));
+    let d1 = Cross(Sub(c, b), Sub(p, b));
+    let d2 = Cross(Sub(a, c), Sub(p, c));
+
+    let hasNegative = d0 < 0.0 || d1 < 0.0 || d2 < 0.0;
+    let hasPositive = d0 > 0.0 || d1 > 0.0 || d2 > 0.0;
+    !(hasNegative && hasPositive)
+}
+
+fn Add(a : Point, b : Point) -> Point { [ a[0] + b[0], a[1] + b[1] ] }
+fn Sub(a : Point, b : Point) -> Point { [ a[0] - b[0], a[1] - b[1] ] }
+fn Scale(a : Point, s : f32) -> Point { [ a[0] * s, a[1] * s ] }
+fn Dot(a : Point, b : Point) -> f32 { a[0] * b[0] + a[1] * b[1] }
+fn Cross(a : Point, b : Point) -> f32 { a[0] * b[1] - a[1] * b[0] }
+fn Length(a : Point) -> f32 { Dot(a, a).sqrt() }
+fn LeftNormal(d : Point) -> Point { [ -d[1], d[0] ] }
+
+fn Normalize(a : Point) -> Point {
+    let length = Length(a);
+    if length < EPSILON { [0.0, 0.0] } else { Scale(a, 1.0 / length) }
+}
+
+fn Rotate(a : Point, angle : f32) -> Point {
+    let (sin, cos) = angle.sin_cos();
+    [ a[0] * cos - a[1] * sin, a[0] * sin + a[1] * cos ]
+}
@@ -0,0 +1,27 @@
+#![allow(non_snake_case)]
+
+use std::sync::atomic::{ AtomicBool, Ordering };
+
+use libc;
+
+static SHUTDOWN_REQUESTED : AtomicBool = AtomicBool::new(false);
+
+extern "C" fn OnShutdownSignal(_signal : libc::c_int) {
+    // only async-signal-safe work in here, the main loop picks it up
+    SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+// Routes SIGINT and SIGTERM to ShutdownRequested() instead of killing the
+// process, so the main loop can exit and release everything properly.
+pub fn InstallShutdownHandler() {
+    let handler = OnShutdownSignal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+pub fn ShutdownRequested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::SeqCst)
+}
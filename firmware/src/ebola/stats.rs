@@ -0,0 +1,269 @@
+#![allow(non_snake_case)]
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::time::Duration;
+
+use duration::Millis;
+use error::Result;
+use renderer::{ BlendMode, ComponentType, GPUBuffer, GPUBufferTarget, GPUBufferUsage, PrimitivesType,
+                RenderCommand, RenderPass, RenderState, ShaderStage, VertexLayout };
+
+// Counters filled by the renderer while drawing. The per-frame ones belong
+// to the thread drawing the frame (layers draw on their own thread) and are
+// reset by TakeFrameCounters, the memory ones track the live total of the
+// process.
+thread_local! {
+    static DRAW_CALLS : Cell<usize> = Cell::new(0);
+    static VERTICES : Cell<usize> = Cell::new(0);
+    static STATE_CHANGES : Cell<usize> = Cell::new(0);
+}
+static BUFFER_MEMORY : AtomicUsize = AtomicUsize::new(0);
+static TEXTURE_MEMORY : AtomicUsize = AtomicUsize::new(0);
+
+pub fn CountDrawCall(numVertices : u32) {
+    DRAW_CALLS.with(|count| count.set(count.get() + 1));
+    VERTICES.with(|count| count.set(count.get() + numVertices as usize));
+}
+
+// program, buffer and texture binds as well as render state changes
+pub fn CountStateChange() {
+    STATE_CHANGES.with(|count| count.set(count.get() + 1));
+}
+
+// Draw calls, vertices and state changes of the calling thread since the
+// previous call. Frame loops without RenderStats call it to drop them.
+pub fn TakeFrameCounters() -> (usize, usize, usize) {
+    (DRAW_CALLS.with(|count| count.replace(0)),
+     VERTICES.with(|count| count.replace(0)),
+     STATE_CHANGES.with(|count| count.replace(0)))
+}
+
+pub fn TrackBufferMemory(allocated : usize, released : usize) {
+    BUFFER_MEMORY.fetch_add(allocated, Ordering::Relaxed);
+    BUFFER_MEMORY.fetch_sub(released, Ordering::Relaxed);
+}
+
+pub fn TrackTextureMemory(allocated : usize, released : usize) {
+    TEXTURE_MEMORY.fetch_add(allocated, Ordering::Relaxed);
+    TEXTURE_MEMORY.fetch_sub(released, Ordering::Relaxed);
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct FrameStats {
+    // time between the start of this and the previous frame
+    pub frameTime : Duration,
+    // CPU time spent in Application::update
+    pub updateTime : Duration,
+    // CPU time spent submitting the render passes
+    pub renderTime : Duration,
+    // time blocked in eglSwapBuffers, mostly waiting for the vertical blank
+    pub swapTime : Duration,
+    pub drawCalls : usize,
+    pub vertices : usize,
+    pub stateChanges : usize,
+    // bytes currently allocated in buffers and textures
+    pub bufferMemory : usize,
+    pub textureMemory : usize,
+}
+
+// Keeps the statistics of the last rendered frames
+pub struct RenderStats {
+    history : VecDeque<FrameStats>,
+    historyLength : usize,
+}
+
+impl RenderStats {
+
+    pub fn new(historyLength : usize) -> RenderStats {
+        RenderStats {
+            history: VecDeque::with_capacity(historyLength),
+            historyLength: historyLength,
+        }
+    }
+
+    // Collects the counters of the frame the calling thread just rendered and
+    // resets them for the next one.
+    pub fn EndFrame(& mut self, frameTime : Duration, updateTime : Duration, renderTime : Duration, swapTime : Duration) {
+
+        let (drawCalls, vertices, stateChanges) = TakeFrameCounters();
+
+        let stats = FrameStats {
+            frameTime: frameTime,
+            updateTime: updateTime,
+            renderTime: renderTime,
+            swapTime: swapTime,
+            drawCalls: drawCalls,
+            vertices: vertices,
+            stateChanges: stateChanges,
+            bufferMemory: BUFFER_MEMORY.load(Ordering::Relaxed),
+            textureMemory: TEXTURE_MEMORY.load(Ordering::Relaxed),
+        };
+
+        if self.history.len() == self.historyLength {
+            self.history.pop_front();
+        }
+        self.history.push_back(stats);
+    }
+
+    pub fn Last(& self) -> FrameStats {
+        self.history.back().cloned().unwrap_or_default()
+    }
+
+    // oldest frame first
+    pub fn History(& self) -> & VecDeque<FrameStats> {
+        & self.history
+    }
+
+    // Average over the history, memory values are the latest ones.
+    pub fn Average(& self) -> FrameStats {
+
+        let count = self.history.len();
+        if count == 0 {
+            return FrameStats::default();
+        }
+
+        let mut sum = FrameStats::default();
+        for stats in self.history.iter() {
+            sum.frameTime += stats.frameTime;
+            sum.updateTime += stats.updateTime;
+            sum.renderTime += stats.renderTime;
+            sum.swapTime += stats.swapTime;
+            sum.drawCalls += stats.drawCalls;
+            sum.vertices += stats.vertices;
+            sum.stateChanges += stats.stateChanges;
+        }
+
+        let last = self.Last();
+
+        FrameStats {
+            frameTime: sum.frameTime / count as u32,
+            updateTime: sum.updateTime / count as u32,
+            renderTime: sum.renderTime / count as u32,
+            swapTime: sum.swapTime / count as u32,
+            drawCalls: sum.drawCalls / count,
+            vertices: sum.vertices / count,
+            stateChanges: sum.stateChanges / count,
+            bufferMemory: last.bufferMemory,
+            textureMemory: last.textureMemory,
+        }
+    }
+
+    // One line summary of the averages, meant for logging.
+    pub fn Summary(& self) -> String {
+        let avg = self.Average();
+
+        format!("frame {:.1}ms (update {:.1}ms, render {:.1}ms, swap {:.1}ms), {} draws, {} vertices, {} state changes, buffers {}KB, textures {}KB",
+                Millis(avg.frameTime), Millis(avg.updateTime), Millis(avg.renderTime), Millis(avg.swapTime),
+                avg.drawCalls, avg.vertices, avg.stateChanges,
+                avg.bufferMemory / 1024, avg.textureMemory / 1024)
+    }
+}
+
+////////////////////////////////////
+// Overlay
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct OverlayVertex {
+    pos : [f32; 2],
+    color : [u8; 4],
+}
+
+// vertices of a quad drawn as two triangles
+const QUAD_VERTICES : usize = 6;
+
+// Draws the update and render time of the last frames as a bar graph. The
+// horizontal line marks the frame budget. Requires a shader taking the
+// position in pixels as 'a_vertex' and a vertex color as 'a_color'.
+pub struct StatsOverlay {
+    buffer : Rc<GPUBuffer>,
+    numBars : usize,
+    x : f32,
+    y : f32,
+    width : f32,
+    height : f32,
+    // frame time that fills the whole height
+    budget : Duration,
+}
+
+impl StatsOverlay {
+
+    // Returns the overlay and the pass drawing it, the pass has to be added
+    // to the RenderContext.
+    pub fn new(shader : & Rc<ShaderStage>, rect : [f32; 4], numBars : usize, budget : Duration, order : i32) -> Result<(StatsOverlay, RenderPass)> {
+
+        // background, budget line and two stacked quads per bar
+        let numVertices = (2 + numBars * 2) * QUAD_VERTICES;
+        let vertices = vec![OverlayVertex { pos: [0.0, 0.0], color: [0, 0, 0, 0] }; numVertices];
+        let buffer = Rc::new(GPUBuffer::new(& vertices, GPUBufferTarget::Array, GPUBufferUsage::Dynamic));
+
+        let layout = VertexLayout::new()
+                        .Add("a_vertex", 2, ComponentType::Float, false)
+                        .Add("a_color", 4, ComponentType::UnsignedByte, true);
+
+        let command = RenderCommand::new(shader.BindVertexLayout(& layout, & buffer)?, vec![], PrimitivesType::Triangles, numVertices as u32)
+                        .WithRenderState(RenderState::Blended(BlendMode::Alpha));
+
+        let pass = RenderPass::new("stats", shader.clone(), order).WithCommands(vec![command]);
+
+        let overlay = StatsOverlay {
+            buffer: buffer,
+            numBars: numBars,
+            x: rect[0],
+            y: rect[1],
+            width: rect[2],
+            height: rect[3],
+            budget: budget,
+        };
+
+        Ok((overlay, pass))
+    }
+
+    // Regenerates the bars from the history. Call before rendering.
+    pub fn Update(& self, stats : & RenderStats) {
+
+        let mut vertices = Vec::with_capacity((2 + self.numBars * 2) * QUAD_VERTICES);
+        let barWidth = self.width / self.numBars as f32;
+        let bottom = self.y + self.height;
+        let budgetMs = Millis(self.budget);
+
+        // background
+        PushQuad(& mut vertices, self.x, self.y, self.width, self.height, [0, 0, 0, 160]);
+        // budget line at 100%
+        PushQuad(& mut vertices, self.x, self.y, self.width, 1.0, [255, 255, 255, 200]);
+
+        let history = stats.History();
+        let skip = history.len().saturating_sub(self.numBars);
+
+        for i in 0 .. self.numBars {
+            let x = self.x + i as f32 * barWidth;
+
+            let (updateMs, renderMs) = match history.get(skip + i) {
+                Some(frame) => (Millis(frame.updateTime), Millis(frame.renderTime)),
+                None => (0.0, 0.0),
+            };
+
+            let updateHeight = (updateMs / budgetMs).min(1.0) * self.height;
+            let renderHeight = (renderMs / budgetMs).min(1.0) * self.height;
+            let overBudget = updateMs + renderMs > budgetMs;
+            let renderColor = if overBudget { [255, 64, 64, 255] } else { [64, 255, 64, 255] };
+
+            PushQuad(& mut vertices, x, bottom - updateHeight, barWidth - 1.0, updateHeight, [64, 128, 255, 255]);
+            PushQuad(& mut vertices, x, bottom - updateHeight - renderHeight, barWidth - 1.0, renderHeight, renderColor);
+        }
+
+        self.buffer.Update(& vertices);
+    }
+}
+
+fn PushQuad(vertices : & mut Vec<OverlayVertex>, x : f32, y : f32, width : f32, height : f32, color : [u8; 4]) {
+    let corners = [ [x, y], [x, y + height], [x + width, y + height],
+                    [x, y], [x + width, y + height], [x + width, y] ];
+
+    for corner in corners.iter() {
+        vertices.push(OverlayVertex { pos: *corner, color: color });
+    }
+}
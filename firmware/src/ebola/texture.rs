@@ -2,6 +2,7 @@
 
 extern crate image;
 
+use std::path::Path;
 use std::time::Instant;
 
 use opengles::glesv2 as gl;
@@ -9,55 +10,293 @@ use opengles::glesv2 as gl;
 use gl::GLuint;
 use gl::GLenum;
 
-//use self::image::GenericImage;
-use self::image::GenericImageView;
+use error::{ Error, Result };
+use etc1;
+use resources;
+use resources::ResourceKind;
+use stats;
+
+use self::image::DynamicImage;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PixelFormat {
+    Luminance,
+    LuminanceAlpha,
+    RGB,
+    RGBA,
+}
+
+fn PixelFormatToGL(vit : & PixelFormat) -> GLenum {
+    match *vit {
+        PixelFormat::Luminance => gl::GL_LUMINANCE,
+        PixelFormat::LuminanceAlpha => gl::GL_LUMINANCE_ALPHA,
+        PixelFormat::RGB => gl::GL_RGB,
+        PixelFormat::RGBA => gl::GL_RGBA,
+    }
+}
+
+pub fn BytesPerPixel(vit : & PixelFormat) -> u32 {
+    match *vit {
+        PixelFormat::Luminance => 1,
+        PixelFormat::LuminanceAlpha => 2,
+        PixelFormat::RGB => 3,
+        PixelFormat::RGBA => 4,
+    }
+}
+
+// Compressed pixels keep the PixelFormat of the image they were made from
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextureCompression {
+    None,
+    // RGB only, see etc1.rs
+    ETC1,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TextureFilter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TextureWrap {
+    ClampToEdge,
+    Repeat,
+    MirroredRepeat,
+}
+
+fn WrapToGL(vit : & TextureWrap) -> GLenum {
+    match *vit {
+        TextureWrap::ClampToEdge => gl::GL_CLAMP_TO_EDGE,
+        TextureWrap::Repeat => gl::GL_REPEAT,
+        TextureWrap::MirroredRepeat => gl::GL_MIRRORED_REPEAT,
+    }
+}
+
+#[derive(Clone)]
+pub struct TextureOptions {
+    pub minFilter : TextureFilter,
+    pub magFilter : TextureFilter,
+    pub wrapS : TextureWrap,
+    pub wrapT : TextureWrap,
+    // multiply the color channels by alpha while loading, to be used
+    // with premultiplied alpha blending
+    pub premultiplyAlpha : bool,
+    // only applied to power-of-two images, GLES2 can't mipmap others
+    pub generateMipmaps : bool,
+}
+
+impl Default for TextureOptions {
+    fn default() -> TextureOptions {
+        TextureOptions {
+            minFilter: TextureFilter::Linear,
+            magFilter: TextureFilter::Linear,
+            wrapS: TextureWrap::ClampToEdge,
+            wrapT: TextureWrap::ClampToEdge,
+            premultiplyAlpha: false,
+            generateMipmaps: false,
+        }
+    }
+}
+
+// Decoded image data, ready to be uploaded to the GPU
+pub struct TextureData {
+    pub width : u32,
+    pub height : u32,
+    pub format : PixelFormat,
+    pub compression : TextureCompression,
+    pub pixels : Vec<u8>,
+}
+
+// GLES2 guarantees 8 texture units for the fragment shader
+pub const MAX_TEXTURE_UNITS : usize = 8;
 
 pub struct Texture
 {
     pub identifier : GLuint,
-    pub unit : i32,
-    sampler : GLenum,
+    pub width : u32,
+    pub height : u32,
+    pub format : PixelFormat,
+    compression : TextureCompression,
+    hasMipmaps : bool,
+}
+
+impl Texture {
+
+    // binds the texture to the given texture unit
+    pub fn Bind(& self, unit : u32) {
+        gl::active_texture(gl::GL_TEXTURE0 + unit);
+        gl::bind_texture(gl::GL_TEXTURE_2D, self.identifier);
+    }
+
+    // estimated video memory used by the texture in bytes
+    pub fn GpuMemory(& self) -> usize {
+        let baseLevel = match self.compression {
+            TextureCompression::None => (self.width * self.height * BytesPerPixel(& self.format)) as usize,
+            TextureCompression::ETC1 => etc1::CompressedSize(self.width, self.height),
+        };
+        
+        // the whole mip chain adds a third of the base level
+        if self.hasMipmaps { baseLevel * 4 / 3 } else { baseLevel }
+    }
+}
+
+impl Drop for Texture {
+    fn drop(& mut self) {
+        gl::delete_textures(&[self.identifier]);
+        resources::TrackRelease(ResourceKind::Texture);
+        stats::TrackTextureMemory(0, self.GpuMemory());
+    }
 }
 
-pub fn LoadTexture(path : &str, samplerIdx : i32) -> Texture {
+pub fn LoadTexture(path : &str) -> Result<Texture> {
+    LoadTextureWithOptions(path, & TextureOptions::default())
+}
+
+pub fn LoadTextureWithOptions(path : &str, options : & TextureOptions) -> Result<Texture> {
 
     let startTime = Instant::now();
 
-    let img = image::open(path).expect("Failed to load texture");
-    let imgData = img.raw_pixels();
-
-    let samplerEnum = match samplerIdx {
-        0 => gl::GL_TEXTURE0,
-        1 => gl::GL_TEXTURE1,
-        2 => gl::GL_TEXTURE2,
-        3 => gl::GL_TEXTURE3,
-        4 => gl::GL_TEXTURE4,
-        5 => gl::GL_TEXTURE5,
-        6 => gl::GL_TEXTURE6,
-        7 => gl::GL_TEXTURE7,
-        _ => panic!("Possibly unsupported sampler index")
+    let data = DecodeTexture(path, options)?;
+    let texture = UploadTexture(& data, options)?;
+
+    println!("LoadTexture({}) {}x{} {:?} -- {}ms", path, data.width, data.height, data.format, startTime.elapsed().as_millis());
+
+    Ok(texture)
+}
+
+// Reads and decodes the image file. Luminance, luminance-alpha, RGB and RGBA
+// images are kept in their format, everything else is converted to RGBA.
+pub fn DecodeTexture(path : &str, options : & TextureOptions) -> Result<TextureData> {
+
+    if !Path::new(path).exists() {
+        return Err(Error::FileNotFound(path.to_owned()));
+    }
+
+    let img = match image::open(path) {
+        Ok(img) => img,
+        Err(err) => return Err(Error::ImageDecode(path.to_owned(), err))
+    };
+
+    let (width, height, format, mut pixels) = match img {
+        DynamicImage::ImageLuma8(buf) => (buf.width(), buf.height(), PixelFormat::Luminance, buf.into_raw()),
+        DynamicImage::ImageLumaA8(buf) => (buf.width(), buf.height(), PixelFormat::LuminanceAlpha, buf.into_raw()),
+        DynamicImage::ImageRgb8(buf) => (buf.width(), buf.height(), PixelFormat::RGB, buf.into_raw()),
+        DynamicImage::ImageRgba8(buf) => (buf.width(), buf.height(), PixelFormat::RGBA, buf.into_raw()),
+        other => {
+            let buf = other.to_rgba();
+            (buf.width(), buf.height(), PixelFormat::RGBA, buf.into_raw())
+        }
     };
 
+    if options.premultiplyAlpha {
+        PremultiplyAlpha(format, & mut pixels);
+    }
+
+    Ok(TextureData {
+        width: width,
+        height: height,
+        format: format,
+        compression: TextureCompression::None,
+        pixels: pixels,
+    })
+}
+
+fn PremultiplyAlpha(format : PixelFormat, pixels : & mut [u8]) {
+
+    let bpp = BytesPerPixel(& format) as usize;
+
+    if format != PixelFormat::RGBA && format != PixelFormat::LuminanceAlpha {
+        return;
+    }
+
+    for pixel in pixels.chunks_mut(bpp) {
+        let alpha = pixel[bpp - 1] as u32;
+        for channel in pixel[.. bpp - 1].iter_mut() {
+            *channel = ((*channel as u32 * alpha + 127) / 255) as u8;
+        }
+    }
+}
+
+fn IsPowerOfTwo(value : u32) -> bool {
+    value != 0 && (value & (value - 1)) == 0
+}
+
+// Creates a GL texture from the decoded data. Has to be called on the thread
+// owning the GL context. The texture unit is assigned when drawing.
+pub fn UploadTexture(data : & TextureData, options : & TextureOptions) -> Result<Texture> {
+
     // create the texture identifier
     let textures = gl::gen_textures(1);
+    resources::TrackCreate(ResourceKind::Texture);
 
-    // bind samples to texture identifier
-    gl::active_texture(samplerEnum);
+    let useMipmaps = UploadPixels(textures[0], data, options);
 
-    gl::bind_texture(gl::GL_TEXTURE_2D, textures[0]);
+    let texture = Texture {
+        identifier: textures[0],
+        width: data.width,
+        height: data.height,
+        format: data.format,
+        compression: data.compression,
+        hasMipmaps: useMipmaps,
+    };
 
-    gl::tex_parameteri(gl::GL_TEXTURE_2D, gl::GL_TEXTURE_MIN_FILTER, gl::GL_LINEAR as i32);
-    gl::tex_parameteri(gl::GL_TEXTURE_2D, gl::GL_TEXTURE_MAG_FILTER, gl::GL_LINEAR as i32);
-    gl::tex_parameteri(gl::GL_TEXTURE_2D, gl::GL_TEXTURE_WRAP_S, gl::GL_CLAMP_TO_EDGE as i32);
-    gl::tex_parameteri(gl::GL_TEXTURE_2D, gl::GL_TEXTURE_WRAP_T, gl::GL_CLAMP_TO_EDGE as i32);
- 
-    gl::tex_image_2d(gl::GL_TEXTURE_2D, 0, gl::GL_RGB as i32, img.width() as i32, img.height() as i32, 0, gl::GL_RGB, gl::GL_UNSIGNED_BYTE, &imgData[..]);
+    stats::TrackTextureMemory(texture.GpuMemory(), 0);
 
-    println!("LoadTexture({}) into Sampler: {} -- {}ms", path, samplerIdx, startTime.elapsed().as_millis());
-    
-    Texture {
-        identifier: textures[0],
-        unit: samplerIdx,
-        sampler: samplerEnum,
+    Ok(texture)
+}
+
+// Specifies the image and sampling parameters of the texture, returns
+// whether mipmaps were generated.
+fn UploadPixels(identifier : GLuint, data : & TextureData, options : & TextureOptions) -> bool {
+
+    // GLES2 only supports mipmaps and repeat modes on power-of-two textures
+    let isPowerOfTwo = IsPowerOfTwo(data.width) && IsPowerOfTwo(data.height);
+    // compressed textures can't be mipmapped by the GPU
+    let isCompressed = data.compression != TextureCompression::None;
+    let useMipmaps = options.generateMipmaps && isPowerOfTwo && !isCompressed;
+
+    let (wrapS, wrapT) = if isPowerOfTwo {
+        (options.wrapS, options.wrapT)
+    } else {
+        (TextureWrap::ClampToEdge, TextureWrap::ClampToEdge)
+    };
+
+    let minFilter = match (options.minFilter, useMipmaps) {
+        (TextureFilter::Nearest, false) => gl::GL_NEAREST,
+        (TextureFilter::Linear, false) => gl::GL_LINEAR,
+        (TextureFilter::Nearest, true) => gl::GL_NEAREST_MIPMAP_NEAREST,
+        (TextureFilter::Linear, true) => gl::GL_LINEAR_MIPMAP_LINEAR,
+    };
+
+    let magFilter = match options.magFilter {
+        TextureFilter::Nearest => gl::GL_NEAREST,
+        TextureFilter::Linear => gl::GL_LINEAR,
+    };
+
+    gl::bind_texture(gl::GL_TEXTURE_2D, identifier);
+
+    gl::tex_parameteri(gl::GL_TEXTURE_2D, gl::GL_TEXTURE_MIN_FILTER, minFilter as i32);
+    gl::tex_parameteri(gl::GL_TEXTURE_2D, gl::GL_TEXTURE_MAG_FILTER, magFilter as i32);
+    gl::tex_parameteri(gl::GL_TEXTURE_2D, gl::GL_TEXTURE_WRAP_S, WrapToGL(& wrapS) as i32);
+    gl::tex_parameteri(gl::GL_TEXTURE_2D, gl::GL_TEXTURE_WRAP_T, WrapToGL(& wrapT) as i32);
+
+    // rows of RGB and luminance images are not 4 byte aligned
+    gl::pixel_storei(gl::GL_UNPACK_ALIGNMENT, 1);
+
+    match data.compression {
+        TextureCompression::None => {
+            let glFormat = PixelFormatToGL(& data.format);
+            gl::tex_image_2d(gl::GL_TEXTURE_2D, 0, glFormat as i32, data.width as i32, data.height as i32, 0, glFormat, gl::GL_UNSIGNED_BYTE, &data.pixels[..]);
+        },
+        TextureCompression::ETC1 => {
+            gl::compressed_tex_image_2d(gl::GL_TEXTURE_2D, 0, etc1::GL_ETC1_RGB8_OES, data.width as i32, data.height as i32, 0, &data.pixels[..]);
+        },
+    }
+
+    if useMipmaps {
+        gl::generate_mipmap(gl::GL_TEXTURE_2D);
     }
-}
\ No newline at end of file
+
+    useMipmaps
+}
@@ -0,0 +1,264 @@
+#![allow(non_snake_case)]
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use duration;
+use error::{ Error, Result };
+
+// RGBA, 0..1
+pub type Color = [f32; 4];
+
+// returned for names missing in a palette, hard to overlook on screen
+const MISSING_COLOR : Color = [1.0, 0.0, 1.0, 1.0];
+
+// RGBA bytes for vertex colors
+pub fn ToColorBytes(color : Color) -> [u8; 4] {
+    [ (color[0] * 255.0) as u8, (color[1] * 255.0) as u8, (color[2] * 255.0) as u8, (color[3] * 255.0) as u8 ]
+}
+
+// Named colors, loaded from a '.palette' file with one 'name = #RRGGBB[AA]'
+// per line. Lines starting with '#' are comments.
+pub struct Palette {
+    pub name : String,
+    colors : HashMap<String, Color>,
+}
+
+impl Palette {
+
+    // Palette defined in code, e.g. as fallback for missing palette files
+    pub fn FromColors(name : & str, colors : &[(& str, Color)]) -> Palette {
+        Palette {
+            name: name.to_owned(),
+            colors: colors.iter().map(|& (name, color)| (name.to_owned(), color)).collect(),
+        }
+    }
+
+    pub fn Load(path : & str) -> Result<Palette> {
+
+        if !Path::new(path).exists() {
+            return Err(Error::FileNotFound(path.to_owned()));
+        }
+
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => return Err(Error::Io(path.to_owned(), err))
+        };
+
+        let mut colors = HashMap::new();
+
+        for (idx, line) in content.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with("#") {
+                continue;
+            }
+
+            let invalid = Error::InvalidPalette { path: path.to_owned(), line: idx + 1 };
+
+            let pair : Vec<_> = line.split("=").collect();
+            if pair.len() != 2 {
+                return Err(invalid);
+            }
+
+            match ParseColor(pair[1].trim()) {
+                Some(color) => { colors.insert(pair[0].trim().to_owned(), color); },
+                None => return Err(invalid),
+            }
+        }
+
+        let name = Path::new(path).file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+
+        Ok(Palette {
+            name: name,
+            colors: colors,
+        })
+    }
+
+    pub fn Get(& self, name : & str) -> Option<Color> {
+        self.colors.get(name).cloned()
+    }
+}
+
+// '#RRGGBB' or '#RRGGBBAA'
+fn ParseColor(value : & str) -> Option<Color> {
+
+    if !value.starts_with("#") || (value.len() != 7 && value.len() != 9) {
+        return None;
+    }
+
+    let mut color = [1.0; 4];
+    for (i, channel) in color.iter_mut().enumerate().take((value.len() - 1) / 2) {
+        let byte = u8::from_str_radix(value.get(1 + i * 2 .. 3 + i * 2)?, 16).ok()?;
+        *channel = byte as f32 / 255.0;
+    }
+
+    Some(color)
+}
+
+// The palettes of the application. Widgets ask for colors by name, switching
+// the palette cross-fades from the old to the new colors.
+pub struct Theme {
+    palettes : HashMap<String, Palette>,
+    current : String,
+    // colors shown when the fade started, None when no fade is running
+    previous : Option<Palette>,
+    // used for colors the current palette doesn't define
+    fallback : Option<Palette>,
+    // 0 shows the previous palette, 1 the current one
+    fadeProgress : f32,
+    fadeDuration : Duration,
+}
+
+impl Theme {
+
+    // A theme without palettes, every color is missing.
+    pub fn new() -> Theme {
+        Theme {
+            palettes: HashMap::new(),
+            current: String::new(),
+            previous: None,
+            fallback: None,
+            fadeProgress: 1.0,
+            fadeDuration: Duration::from_secs(0),
+        }
+    }
+
+    // Colors missing in the current palette are taken from the fallback
+    // instead of showing up as MISSING_COLOR.
+    pub fn WithFallback(mut self, fallback : Palette) -> Theme {
+        self.fallback = Some(fallback);
+        self
+    }
+
+    // Loads all '.palette' files of the directory, the file name without the
+    // extension becomes the name of the palette. Files that fail to load are
+    // skipped, without the initial palette the fallback colors are shown.
+    pub fn LoadDirectory(dirPath : & str, initial : & str, fallback : Palette) -> Theme {
+
+        let mut theme = Theme::new().WithFallback(fallback);
+        theme.current = initial.to_owned();
+
+        let entries = match fs::read_dir(dirPath) {
+            Ok(entries) => entries,
+            Err(err) => {
+                println!("Using the built-in palette. {}", Error::Io(dirPath.to_owned(), err));
+                return theme;
+            }
+        };
+
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+
+            if path.extension().map_or(false, |ext| ext == "palette") {
+                match Palette::Load(& path.to_string_lossy()) {
+                    Ok(palette) => { theme.palettes.insert(palette.name.clone(), palette); },
+                    Err(err) => println!("Skipping palette. {}", err),
+                }
+            }
+        }
+
+        if !theme.palettes.contains_key(initial) {
+            println!("Using the built-in palette. {}", Error::PaletteNotFound(initial.to_owned()));
+        }
+
+        theme.ReportMissingColors();
+
+        theme
+    }
+
+    // every palette should define the same names, else widgets show up
+    // in MISSING_COLOR after a switch
+    fn ReportMissingColors(& self) {
+        for palette in self.palettes.values() {
+            for other in self.palettes.values() {
+                for name in other.colors.keys().filter(|name| !palette.colors.contains_key(*name)) {
+                    println!("Palette '{}' is missing color '{}' defined in '{}'", palette.name, name, other.name);
+                }
+            }
+        }
+    }
+
+    pub fn Current(& self) -> & str {
+        & self.current
+    }
+
+    // Switches to the palette, fading over the given time. Switching during a
+    // fade starts from the blended colors on screen.
+    pub fn SetPalette(& mut self, name : & str, fadeTime : Duration) -> Result<()> {
+
+        if !self.palettes.contains_key(name) {
+            return Err(Error::PaletteNotFound(name.to_owned()));
+        }
+
+        if name == self.current {
+            return Ok(());
+        }
+
+        let mut names : Vec<& String> = self.palettes.values().chain(self.fallback.iter()).flat_map(|palette| palette.colors.keys()).collect();
+        names.sort();
+        names.dedup();
+
+        let snapshot : HashMap<String, Color> = names.into_iter().map(|color| (color.clone(), self.Color(color))).collect();
+
+        self.previous = Some(Palette {
+            name: self.current.clone(),
+            colors: snapshot,
+        });
+        self.current = name.to_owned();
+        self.fadeProgress = 0.0;
+        self.fadeDuration = fadeTime;
+
+        Ok(())
+    }
+
+    pub fn IsFading(& self) -> bool {
+        self.previous.is_some()
+    }
+
+    // Advances the fade. Returns whether the colors changed.
+    pub fn Update(& mut self, dt : Duration) -> bool {
+
+        if self.previous.is_none() {
+            return false;
+        }
+
+        let fadeTime = duration::Seconds(self.fadeDuration);
+        let step = duration::Seconds(dt);
+
+        self.fadeProgress = if fadeTime > 0.0 { (self.fadeProgress + step / fadeTime).min(1.0) } else { 1.0 };
+
+        if self.fadeProgress >= 1.0 {
+            self.previous = None;
+        }
+
+        true
+    }
+
+    // The color of the current palette, blended with the previous one while
+    // fading.
+    pub fn Color(& self, name : & str) -> Color {
+
+        let current = self.PaletteColor(& self.current, name);
+
+        match self.previous {
+            Some(ref previous) => {
+                let from = previous.Get(name).unwrap_or(current);
+                let t = self.fadeProgress;
+                [ from[0] + (current[0] - from[0]) * t,
+                  from[1] + (current[1] - from[1]) * t,
+                  from[2] + (current[2] - from[2]) * t,
+                  from[3] + (current[3] - from[3]) * t ]
+            },
+            None => current,
+        }
+    }
+
+    fn PaletteColor(& self, palette : & str, name : & str) -> Color {
+        self.palettes.get(palette).and_then(|palette| palette.Get(name))
+            .or_else(|| self.fallback.as_ref().and_then(|fallback| fallback.Get(name)))
+            .unwrap_or(MISSING_COLOR)
+    }
+}
@@ -5,115 +5,1066 @@ extern crate videocore;
 extern crate ebola;
 extern crate cgmath;
 
+pub mod boot;
+pub mod display;
 pub mod vehicle;
 
+use std::env;
+use std::f32::consts::PI;
+use std::io;
+use std::mem;
+use std::process;
+use std::rc::{ Rc, Weak };
+use std::sync::mpsc;
+use std::sync::mpsc::{ Receiver, TryRecvError };
+use std::thread;
+use std::time::{ Duration, Instant, SystemTime };
+
 use videocore::bcm_host;
 
+use ebola::{ LayerAlpha, LayerDescriptor };
+use ebola::animation::{ Easing, Smoother, Timeline };
+use ebola::app::{ Application, AppContext, Event, RunApplication };
+use ebola::assets::AssetManager;
+use ebola::atlas::{ AtlasBuilder, AtlasRegion, PackedAtlas };
+use ebola::backlight::Backlight;
+use ebola::cache;
+use ebola::chart::{ Chart, ChartColors, ChartStyle };
+use ebola::input::{ Gesture, GestureRecognizer, SwipeDirection, TouchScreen };
+use ebola::layer;
+use ebola::layer::{ LayerApplication, LayerContext, LayerHandle };
+use ebola::pages::{ PageManager, Transition };
 use ebola::renderer;
+use ebola::scheduler::FrameScheduler;
+use ebola::shapes::{ LineJoin, Shape, ShapeBuilder };
+use ebola::stats::StatsOverlay;
+use ebola::texture::TextureOptions;
+use ebola::theme::{ Color, Palette, Theme, ToColorBytes };
 use ebola::renderer::{
         RenderContext,
         RenderCommand,
+        RenderPass,
         PrimitivesType,
         GPUBuffer,
         GPUBufferTarget,
-        GPUBufferUsage 
+        GPUBufferUsage,
+        VertexLayout,
+        RenderState,
+        BlendMode 
     };
 
 type Vector2 = cgmath::Vector2<f32>;
-type Vector3 = cgmath::Vector3<f32>;
 
 const DATA_PATH : & str = "/opt/firmware/data" ;
 
+// size of the UI in pixels, must match projection.glsl
+const SCREEN_SIZE : [f32; 2] = [1024.0, 600.0];
+
+const TARGET_FPS : u32 = 30;
+
+// render order of the passes, lower is drawn first
+const WORLD_PASS_ORDER : i32 = 0;
+const SPEEDOMETER_PASS_ORDER : i32 = 50;
+const CHART_PASS_ORDER : i32 = 60;
+const UI_PASS_ORDER : i32 = 100;
+const STATS_PASS_ORDER : i32 = 1000;
+
+// dispmanx layer of the warnings, on top of the dashboard
+const WARNING_LAYER : i32 = 4;
+
+// battery charge in percent below which the warning is shown
+const LOW_BATTERY_THRESHOLD : i32 = 10;
+
+// the warning bar slides in from above the screen
+const WARNING_BAR_HEIGHT : f32 = 40.0;
+const WARNING_SLIDE_MILLIS : u64 = 250;
+
+// time the speed readout needs to catch up with a new sample
+const SPEED_SMOOTH_MILLIS : u64 = 300;
+
+// dial of the speedometer, the scale runs clockwise from 135° to 405°
+const SPEEDOMETER_CENTER : [f32; 2] = [512.0, 300.0];
+const SPEEDOMETER_RADIUS : f32 = 200.0;
+const SPEEDOMETER_MAX_SPEED : i32 = 120;
+const SPEEDOMETER_TICK_STEP : i32 = 10;
+
+// how often the frame statistics are logged with --stats
+const STATS_LOG_INTERVAL_SECS : u64 = 10;
+
+// Interleaved vertex used by all UI elements. Must match 'ui.layout'.
+#[repr(C)]
+struct UIVertex {
+    pos : [f32; 2],
+    color : [u8; 4],
+    texCoord : [f32; 2],
+}
+
 struct Geometry {
-    vertices : GPUBuffer,
-    colors : GPUBuffer,
-    texcoords : GPUBuffer,
+    vertices : Rc<GPUBuffer>,
+}
+
+fn LoadUIVertexLayout(assets : & AssetManager) -> ebola::Result<VertexLayout> {
+    let path = assets.FullPath("ui.layout");
+    let layout = VertexLayout::Load(& path)?;
+
+    if layout.stride as usize != mem::size_of::<UIVertex>() {
+        return Err(ebola::Error::InvalidLayout {
+            path: path,
+            reason: format!("stride is {} bytes, but UIVertex has {} bytes", layout.stride, mem::size_of::<UIVertex>()),
+        });
+    }
+
+    Ok(layout)
+}
+
+// Shown when the palette files are missing or broken, dark like the night
+// palette so it doesn't blind at night
+fn DefaultPalette() -> Palette {
+    Palette::FromColors("default", &[
+        ("background", [0.1, 0.1, 0.1, 1.0]),
+        ("text", [0.8, 0.8, 0.8, 1.0]),
+        ("accent", [0.0, 0.6, 0.0, 1.0]),
+        ("panel", [0.1, 0.2, 0.5, 1.0]),
+        ("warning", [0.9, 0.0, 0.0, 1.0]),
+    ])
 }
 
 fn GetFullDataPath(relPath : & str) -> String {
     format!("{}/{}", DATA_PATH, relPath)
 }
 
-fn CreateUIQuad(pos : Vector2, size : Vector2, color : Vector3) -> Geometry {
+fn QuadVertices(pos : Vector2, size : Vector2, color : Color, uv0 : [f32; 2], uv1 : [f32; 2]) -> [UIVertex; 4] {
+
+    let color = ToColorBytes(color);
+
+    [ UIVertex { pos: [pos.x, pos.y],                   color: color, texCoord: [uv0[0], uv0[1]] },     // top left
+      UIVertex { pos: [pos.x, pos.y + size.y],          color: color, texCoord: [uv0[0], uv1[1]] },     // bottom left
+      UIVertex { pos: [pos.x + size.x, pos.y + size.y], color: color, texCoord: [uv1[0], uv1[1]] },     // bottom right
+      UIVertex { pos: [pos.x + size.x, pos.y],          color: color, texCoord: [uv1[0], uv0[1]] } ]    // top right
+}
+
+struct ThemedQuad {
+    vertices : Weak<GPUBuffer>,
+    pos : Vector2,
+    size : Vector2,
+    uv0 : [f32; 2],
+    uv1 : [f32; 2],
+    // name of the color in the theme's palettes
+    color : & 'static str,
+}
+
+// Creates the UI quads with colors referenced by name and re-uploads their
+// vertex colors when the theme changes. A quad is forgotten once its
+// geometry is dropped.
+struct ThemedQuads {
+    quads : Vec<ThemedQuad>,
+}
+
+impl ThemedQuads {
+
+    fn new() -> ThemedQuads {
+        ThemedQuads {
+            quads: vec![],
+        }
+    }
+
+    fn CreateUIQuad(& mut self, theme : & Theme, pos : Vector2, size : Vector2, color : & 'static str, image : & AtlasRegion) -> Geometry {
+        self.CreateQuad(theme, pos, size, color, image.uvMin, image.uvMax)
+    }
+
+    // Quad for shaders without texture, the texture coordinates stay unused
+    fn CreateColorQuad(& mut self, theme : & Theme, pos : Vector2, size : Vector2, color : & 'static str) -> Geometry {
+        self.CreateQuad(theme, pos, size, color, [0.0, 0.0], [0.0, 0.0])
+    }
 
-    let vertices = [ pos.x, pos.y,                          // top left
-                     pos.x, pos.y + size.y,                 // bottom left
-                     pos.x + size.x, pos.y + size.y,        // bottom right
-                     pos.x + size.x, pos.y] as [f32;8];     // top right
+    fn CreateQuad(& mut self, theme : & Theme, pos : Vector2, size : Vector2, color : & 'static str, uv0 : [f32; 2], uv1 : [f32; 2]) -> Geometry {
 
-    let colors = [ color.x, color.y, color.z, 
-                   color.x, color.y, color.z, 
-                   color.x, color.y, color.z,
-                   color.x, color.y, color.z ] as [f32; 12];
+        let vertices = QuadVertices(pos, size, theme.Color(color), uv0, uv1);
+        let vertexData = Rc::new(renderer::GPUBuffer::new(& vertices, GPUBufferTarget::Array, GPUBufferUsage::Dynamic));
 
-    let texCoords = [  0.0,  0.0,                // top left
-                       0.0,  1.0,                // bottom left
-                       1.0,  1.0,                // bottom right
-                       1.0,  0.0 ] as [f32;8];   // top right
+        self.quads.push(ThemedQuad {
+            vertices: Rc::downgrade(& vertexData),
+            pos: pos,
+            size: size,
+            uv0: uv0,
+            uv1: uv1,
+            color: color,
+        });
 
-    let vertexData = renderer::GPUBuffer::new(& vertices, GPUBufferTarget::Array, GPUBufferUsage::Static);
-    let colorData = renderer::GPUBuffer::new(& colors, GPUBufferTarget::Array, GPUBufferUsage::Static);
-    let texCoordsData = renderer::GPUBuffer::new(& texCoords, GPUBufferTarget::Array, GPUBufferUsage::Static);   
+        Geometry {
+            vertices : vertexData,
+        }
+    }
 
-    Geometry {
-        vertices : vertexData,
-        colors : colorData,
-        texcoords : texCoordsData,
+    fn Refresh(& mut self, theme : & Theme) {
+        self.quads.retain(|quad| {
+            match quad.vertices.upgrade() {
+                Some(buffer) => {
+                    buffer.Update(& QuadVertices(quad.pos, quad.size, theme.Color(quad.color), quad.uv0, quad.uv1));
+                    true
+                },
+                None => false,
+            }
+        });
     }
 }
 
-fn PrepareUIStage() -> (renderer::ShaderStage, Vec<RenderCommand>) {
+// history of a signal, drawn by its own pass on the given page
+struct ChartDefinition {
+    pass : & 'static str,
+    page : & 'static str,
+    signal : vehicle::Signal,
+    rect : [f32; 4],
+    style : ChartStyle,
+}
+
+// the power below the speedometer (regen shows below the zero line), the
+// others on the diagnostics page. The power chart is drawn before the UI
+// pass, so it has to stay above the panel starting at y 550.
+const POWER_CHART : usize = 0;
+const CHARTS : [ChartDefinition; 3] = [
+    ChartDefinition { pass: "powerHistory", page: RIDING_PAGE, signal: vehicle::Signal::Power, rect: [312.0, 460.0, 400.0, 80.0], style: ChartStyle::Area },
+    ChartDefinition { pass: "temperatureHistory", page: DIAGNOSTICS_PAGE, signal: vehicle::Signal::MotorTemperature, rect: [62.0, 60.0, 900.0, 220.0], style: ChartStyle::Line },
+    ChartDefinition { pass: "batteryHistory", page: DIAGNOSTICS_PAGE, signal: vehicle::Signal::BatteryCharge, rect: [62.0, 340.0, 900.0, 220.0], style: ChartStyle::Area },
+];
+const CHART_WINDOW_SECS : u64 = 60;
+const CHART_SAMPLES : usize = 200;
+
+// passes of the charts on the page
+fn ChartPasses(page : & str) -> Vec<& 'static str> {
+    CHARTS.iter().filter(|definition| definition.page == page).map(|definition| definition.pass).collect()
+}
+
+// swiping left and right goes through the pages in this order
+const RIDING_PAGE : & str = "riding";
+const DIAGNOSTICS_PAGE : & str = "diagnostics";
+
+// Dial drawn from vector shapes. The scale only changes with the theme,
+// the needle whenever the displayed speed changes.
+struct Speedometer {
+    scale : Shape,
+    needle : Shape,
+}
+
+impl Speedometer {
+
+    fn new(theme : & Theme, speed : f32) -> Speedometer {
+        Speedometer {
+            scale: Speedometer::BuildScale(theme).Build(GPUBufferUsage::Dynamic),
+            needle: Speedometer::BuildNeedle(theme, speed).Build(GPUBufferUsage::Dynamic),
+        }
+    }
+
+    fn SpeedAngle(speed : f32) -> f32 {
+        let startAngle = 0.75 * PI;
+        let sweep = 1.5 * PI;
+        startAngle + sweep * (speed / SPEEDOMETER_MAX_SPEED as f32).max(0.0).min(1.0)
+    }
+
+    fn BuildScale(theme : & Theme) -> ShapeBuilder {
+        let mut builder = ShapeBuilder::new();
+        let center = SPEEDOMETER_CENTER;
+
+        builder.Arc(center, SPEEDOMETER_RADIUS, 6.0, Speedometer::SpeedAngle(0.0), Speedometer::SpeedAngle(SPEEDOMETER_MAX_SPEED as f32), theme.Color("panel"));
+
+        for speed in (0 ..= SPEEDOMETER_MAX_SPEED).step_by(SPEEDOMETER_TICK_STEP as usize) {
+            let angle = Speedometer::SpeedAngle(speed as f32);
+            let dir = [ angle.cos(), angle.sin() ];
+            let tick = [ [ center[0] + dir[0] * (SPEEDOMETER_RADIUS - 25.0), center[1] + dir[1] * (SPEEDOMETER_RADIUS - 25.0) ],
+                         [ center[0] + dir[0] * (SPEEDOMETER_RADIUS - 8.0), center[1] + dir[1] * (SPEEDOMETER_RADIUS - 8.0) ] ];
+            builder.Polyline(& tick, 4.0, LineJoin::Miter, false, theme.Color("text"));
+        }
+
+        builder
+    }
+
+    fn BuildNeedle(theme : & Theme, speed : f32) -> ShapeBuilder {
+        let mut builder = ShapeBuilder::new();
+        let center = SPEEDOMETER_CENTER;
+
+        let angle = Speedometer::SpeedAngle(speed);
+        let dir = [ angle.cos(), angle.sin() ];
+        let side = [ -dir[1], dir[0] ];
+
+        let tipLength = SPEEDOMETER_RADIUS - 30.0;
+        let tailLength = 20.0;
+        let width = 6.0;
+
+        builder.ConvexPolygon(&[ [ center[0] + dir[0] * tipLength, center[1] + dir[1] * tipLength ],
+                                 [ center[0] + side[0] * width, center[1] + side[1] * width ],
+                                 [ center[0] - dir[0] * tailLength, center[1] - dir[1] * tailLength ],
+                                 [ center[0] - side[0] * width, center[1] - side[1] * width ] ], theme.Color("accent"));
+        builder.Circle(center, 12.0, theme.Color("accent"));
+
+        builder
+    }
+
+    // The needle has the same topology at every speed, so the commands
+    // created for it stay valid.
+    fn SetSpeed(& mut self, theme : & Theme, speed : f32) {
+        self.needle.Update(& Speedometer::BuildNeedle(theme, speed));
+    }
+
+    fn Refresh(& mut self, theme : & Theme, speed : f32) {
+        self.scale.Update(& Speedometer::BuildScale(theme));
+        self.SetSpeed(theme, speed);
+    }
+
+    fn Pass(& self, assets : & mut AssetManager) -> ebola::Result<RenderPass> {
+        let shader = assets.GetShaderVariant("default", &["VERTEX_COLOR", "EDGE_AA"])?;
+        let commands = vec![ self.scale.Command(& shader)?, self.needle.Command(& shader)? ];
+
+        Ok(RenderPass::new("speedometer", shader, SPEEDOMETER_PASS_ORDER).WithCommands(commands))
+    }
+}
+
+fn ThemeChartColors(theme : & Theme) -> ChartColors {
+    let accent = theme.Color("accent");
+    ChartColors {
+        line: accent,
+        fill: [ accent[0], accent[1], accent[2], accent[3] * 0.3 ],
+        axis: theme.Color("text"),
+        marker: theme.Color("warning"),
+    }
+}
+
+fn PrepareChart(assets : & mut AssetManager, theme : & Theme, definition : & ChartDefinition) -> ebola::Result<Chart> {
+    let shader = assets.GetShaderVariant("default", &["VERTEX_COLOR", "EDGE_AA"])?;
+
+    Ok(Chart::new(& shader, definition.rect, Duration::from_secs(CHART_WINDOW_SECS), CHART_SAMPLES)
+        .WithStyle(definition.style)
+        .WithColors(ThemeChartColors(theme)))
+}
+
+// Reads, decodes and packs the icons for the atlas on a worker thread, only
+// the upload in PrepareUIPass has to happen on the render thread.
+fn PackAtlasImages() -> Receiver<ebola::Result<PackedAtlas>> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        // all icons of the data directory end up in a single atlas
+        let mut atlasBuilder = AtlasBuilder::new(512, 2);
+        let result = atlasBuilder.AddDirectory(DATA_PATH, & TextureOptions::default()).and_then(|_| atlasBuilder.Pack());
+        let _ = sender.send(result);
+    });
+
+    receiver
+}
+
+fn PrepareUIPass(assets : & mut AssetManager, packedAtlas : PackedAtlas, theme : & Theme, quads : & mut ThemedQuads) -> ebola::Result<RenderPass> {
     
-    let shaderPath = GetFullDataPath("default");
-    let uiOverlayStage = renderer::LoadShaderStage(& shaderPath).unwrap();
+    let uiOverlayStage = assets.GetShaderVariant("default", &["TEXTURED", "VERTEX_COLOR"])?;
 
-    let texturePath = GetFullDataPath("test.png");
-    let tex = ebola::texture::LoadTexture(& texturePath, 0);
+    let atlas = packedAtlas.Upload(& TextureOptions::default())?;
+
+    for (i, page) in atlas.pages.iter().enumerate() {
+        assets.InsertTexture(& format!("atlas{}", i), page.clone());
+    }
+
+    let testImage = match atlas.Region("test") {
+        Some(region) => *region,
+        None => return Err(ebola::Error::FileNotFound(GetFullDataPath("test.png")))
+    };
+    let tex = atlas.Page(& testImage).clone();
     
-    let greenQuad = CreateUIQuad(Vector2{ x: 0.0, y: 50.0 }, Vector2 { x:100.0, y:100.0 }, Vector3 { x:0.0, y:1.0, z: 0.0 });
-    let greenQuadAttribs = vec![
-        uiOverlayStage.BindAttribute("a_vertex", & greenQuad.vertices, 2),
-        uiOverlayStage.BindAttribute("a_color", & greenQuad.colors, 3),
-        uiOverlayStage.BindAttribute("a_texCoord", & greenQuad.texcoords, 2),
-    ];
-    let greenQuadUniforms = vec![
-        uiOverlayStage.BindUniform("u_tex0", vec![tex.unit]),
-    ];
+    let layout = LoadUIVertexLayout(assets)?;
+    uiOverlayStage.ValidateLayout(& layout)?;
 
-    let blueQuad = CreateUIQuad(Vector2{ x: 10.0, y: 550.0 }, Vector2 { x:1004.0, y:500.0 }, Vector3 { x:0.0, y:0.0, z: 1.0 });
-    let blueQuadAttribs = vec![
-        uiOverlayStage.BindAttribute("a_vertex", & blueQuad.vertices, 2),
-        uiOverlayStage.BindAttribute("a_color", & blueQuad.colors, 3),
-        uiOverlayStage.BindAttribute("a_texCoord", & blueQuad.texcoords, 2),
+    let greenQuad = quads.CreateUIQuad(theme, Vector2{ x: 0.0, y: 50.0 }, Vector2 { x:100.0, y:100.0 }, "accent", & testImage);
+    let greenQuadAttribs = uiOverlayStage.BindVertexLayout(& layout, & greenQuad.vertices)?;
+    let greenQuadTextures = vec![
+        uiOverlayStage.BindTexture("u_tex0", & tex)?,
     ];
-    let blueQuadUniforms = vec![
-        uiOverlayStage.BindUniform("u_tex0", vec![tex.unit]),
+
+    let blueQuad = quads.CreateUIQuad(theme, Vector2{ x: 10.0, y: 550.0 }, Vector2 { x:1004.0, y:500.0 }, "panel", & testImage);
+    let blueQuadAttribs = uiOverlayStage.BindVertexLayout(& layout, & blueQuad.vertices)?;
+    let blueQuadTextures = vec![
+        uiOverlayStage.BindTexture("u_tex0", & tex)?,
     ];
 
     let renderCommands = vec![
-        RenderCommand::new(greenQuadAttribs, greenQuadUniforms, PrimitivesType::TriangleFan, 4),
-        RenderCommand::new(blueQuadAttribs, blueQuadUniforms, PrimitivesType::TriangleFan, 4)
+        RenderCommand::new(greenQuadAttribs, vec![], PrimitivesType::TriangleFan, 4)
+            .WithRenderState(RenderState::Blended(BlendMode::Alpha))
+            .WithTextures(greenQuadTextures)?,
+        RenderCommand::new(blueQuadAttribs, vec![], PrimitivesType::TriangleFan, 4).WithTextures(blueQuadTextures)?
     ];
 
-    (uiOverlayStage, renderCommands)
+    Ok(RenderPass::new("ui", uiOverlayStage, UI_PASS_ORDER).WithCommands(renderCommands))
+}
+
+fn PrepareWorldPass(assets : & mut AssetManager) -> ebola::Result<RenderPass> {
+    let geometryStage = assets.GetShader("default")?;
+
+    Ok(RenderPass::new("world", geometryStage, WORLD_PASS_ORDER))
 }
 
-fn PrepareWorldStage() -> (renderer::ShaderStage, Vec<RenderCommand>) {
-    let shaderPath = GetFullDataPath("default");
-    let geometryStage = renderer::LoadShaderStage(& shaderPath).unwrap();
+// First screen shown after EGL is up. It only needs the untextured default
+// shader, the speed is shown as two dashes until the dashboard is loaded.
+fn PrepareSplashScreen(assets : & mut AssetManager, theme : & Theme, quads : & mut ThemedQuads) -> ebola::Result<RenderContext> {
+
+    let splashStage = assets.GetShaderVariant("default", &["VERTEX_COLOR"])?;
+    let layout = LoadUIVertexLayout(assets)?;
+
+    let dashes = [
+        quads.CreateColorQuad(theme, Vector2 { x: 412.0, y: 290.0 }, Vector2 { x: 80.0, y: 20.0 }, "text"),
+        quads.CreateColorQuad(theme, Vector2 { x: 532.0, y: 290.0 }, Vector2 { x: 80.0, y: 20.0 }, "text"),
+    ];
+
+    let mut renderCommands = vec![];
+    for dash in dashes.iter() {
+        let attribs = splashStage.BindVertexLayout(& layout, & dash.vertices)?;
+        renderCommands.push(RenderCommand::new(attribs, vec![], PrimitivesType::TriangleFan, 4));
+    }
+
+    let mut renderContext = RenderContext::new(theme.Color("background"));
+    renderContext.AddPass(RenderPass::new("splash", splashStage, UI_PASS_ORDER).WithCommands(renderCommands));
 
-    (geometryStage, vec![])
+    Ok(renderContext)
+}
+
+// Red bar at the top of the warning layer, the rest of the layer stays
+// transparent. The pass is enabled while the warning is active, the bar
+// starts out above the screen and is moved in by the alert animation.
+fn PrepareWarningPass(assets : & mut AssetManager, color : Color) -> ebola::Result<(RenderPass, Geometry)> {
+
+    let warningStage = assets.GetShaderVariant("default", &["VERTEX_COLOR"])?;
+    let layout = LoadUIVertexLayout(assets)?;
+
+    let vertices = WarningBarVertices(-WARNING_BAR_HEIGHT, color);
+    let bar = Geometry {
+        vertices: Rc::new(GPUBuffer::new(& vertices, GPUBufferTarget::Array, GPUBufferUsage::Dynamic)),
+    };
+    let attribs = warningStage.BindVertexLayout(& layout, & bar.vertices)?;
+
+    let mut pass = RenderPass::new("lowBattery", warningStage, UI_PASS_ORDER)
+                        .WithCommands(vec![ RenderCommand::new(attribs, vec![], PrimitivesType::TriangleFan, 4) ]);
+    pass.enabled = false;
+
+    Ok((pass, bar))
+}
+
+fn WarningBarVertices(offset : f32, color : Color) -> [UIVertex; 4] {
+    QuadVertices(Vector2 { x: 0.0, y: offset }, Vector2 { x: SCREEN_SIZE[0], y: WARNING_BAR_HEIGHT }, color, [0.0, 0.0], [0.0, 0.0])
+}
+
+// Slides the warning bar in, played backwards to hide it again
+fn WarningTimeline() -> Timeline {
+    Timeline::new()
+        .WithSegment("offset", Duration::from_millis(0), Duration::from_millis(WARNING_SLIDE_MILLIS), -WARNING_BAR_HEIGHT, 0.0, Easing::CubicOut)
+}
+
+fn WarningLayerDescriptor() -> LayerDescriptor {
+    LayerDescriptor {
+        layer: WARNING_LAYER,
+        alpha: LayerAlpha::PerPixel,
+        .. LayerDescriptor::default()
+    }
+}
+
+// Sent by the dashboard to the warning layer
+enum WarningMessage {
+    LowBattery(bool),
+    // the theme changed the warning color
+    SetColor(Color),
+}
+
+// Draws the warnings on their own layer and thread, so they keep showing
+// up while the dashboard loads or stalls.
+struct WarningLayer {
+    color : Color,
+    bar : Option<Rc<GPUBuffer>>,
+    timeline : Timeline,
+}
+
+impl WarningLayer {
+
+    fn new(color : Color) -> WarningLayer {
+        WarningLayer {
+            color: color,
+            bar: None,
+            timeline: WarningTimeline(),
+        }
+    }
+
+    fn UpdateBar(& self) {
+        if let Some(ref bar) = self.bar {
+            bar.Update(& WarningBarVertices(self.timeline.Value("offset"), self.color));
+        }
+    }
+}
+
+impl LayerApplication for WarningLayer {
+
+    type Message = WarningMessage;
+
+    fn init(& mut self, ctx : & mut LayerContext) -> ebola::Result<()> {
+        let (pass, bar) = PrepareWarningPass(& mut ctx.assets, self.color)?;
+        ctx.render.AddPass(pass);
+        self.bar = Some(bar.vertices);
+        Ok(())
+    }
+
+    fn handle_message(& mut self, ctx : & mut LayerContext, message : WarningMessage) {
+        match message {
+            WarningMessage::LowBattery(true) => {
+                ctx.render.SetPassEnabled("lowBattery", true);
+                self.timeline.Play();
+            },
+            WarningMessage::LowBattery(false) => self.timeline.PlayReverse(),
+            WarningMessage::SetColor(color) => {
+                self.color = color;
+                self.UpdateBar();
+                ctx.scheduler.RequestRedraw();
+            },
+        }
+    }
+
+    // Slides the warning bar in and out
+    fn update(& mut self, ctx : & mut LayerContext, dt : Duration) {
+
+        if !self.timeline.Update(dt) {
+            return;
+        }
+
+        self.UpdateBar();
+
+        // hidden again, the pass can be skipped
+        if !self.timeline.IsPlaying() && self.timeline.AtStart() {
+            ctx.render.SetPassEnabled("lowBattery", false);
+        }
+        ctx.scheduler.RequestRedraw();
+    }
+}
+
+// Screen shown when the dashboard assets could not be loaded. It does not
+// depend on any file, so the driver at least sees the display is alive.
+fn SafeScreen() -> RenderContext {
+    RenderContext::new([0.1, 0.1, 0.1, 1.0])
+}
+
+// Steps of the startup. After the splash was shown, one step is loaded per
+// frame, so the screen never stays black while the assets load.
+#[derive(Clone, Copy, PartialEq)]
+enum LoadingStage {
+    Splash,
+    World,
+    UI,
+    Done,
+}
+
+// The dashboard shown while riding
+struct Dashboard {
+    config : vehicle::VehicleConfiguration,
+    vehicleData : vehicle::VehicleData,
+    displayConfig : display::DisplayConfiguration,
+    // None if the backlight can't be controlled
+    backlight : Option<Backlight>,
+    dayPhase : display::DayPhase,
+    theme : Theme,
+    themedQuads : ThemedQuads,
+    // speed the needle currently shows in km/h, fractions included so the
+    // needle moves smoothly
+    displayedSpeed : f32,
+    // follows the measured speed, smooths the jumps between samples
+    speedSmoother : Smoother,
+    speedometer : Option<Speedometer>,
+    // in the order of CHARTS, empty until the dashboard is loaded
+    charts : Vec<Chart>,
+    pages : PageManager,
+    lowBatteryShown : bool,
+    // None if the warning layer couldn't be created
+    warnings : Option<LayerHandle<WarningMessage>>,
+    // frame time graph and periodic statistics log, enabled by --stats
+    showStats : bool,
+    statsOverlay : Option<StatsOverlay>,
+    lastStatsLog : Instant,
+    loadingStage : LoadingStage,
+    splashRendered : bool,
+    // the dashboard being loaded, replaces the splash when complete
+    pendingRender : Option<RenderContext>,
+    atlasImages : Option<Receiver<ebola::Result<PackedAtlas>>>,
+    bootLog : boot::BootLog,
+}
+
+impl Dashboard {
+    fn new(config : vehicle::VehicleConfiguration, vehicleData : vehicle::VehicleData, displayConfig : display::DisplayConfiguration, showStats : bool, bootLog : boot::BootLog) -> Dashboard {
+        Dashboard {
+            config: config,
+            vehicleData: vehicleData,
+            displayConfig: displayConfig,
+            backlight: None,
+            dayPhase: display::DayPhase::Day,
+            theme: Theme::new().WithFallback(DefaultPalette()),
+            themedQuads: ThemedQuads::new(),
+            displayedSpeed: 0.0,
+            speedSmoother: Smoother::new(0.0, Duration::from_millis(SPEED_SMOOTH_MILLIS)),
+            speedometer: None,
+            charts: vec![],
+            pages: PageManager::new(SCREEN_SIZE),
+            lowBatteryShown: false,
+            warnings: None,
+            showStats: showStats,
+            statsOverlay: None,
+            lastStatsLog: Instant::now(),
+            loadingStage: LoadingStage::Splash,
+            splashRendered: false,
+            pendingRender: None,
+            atlasImages: None,
+            bootLog: bootLog,
+        }
+    }
+
+    fn SelectDayPhase(& self) -> display::DayPhase {
+        let hour = display::LocalHour(& self.displayConfig, SystemTime::now());
+        display::SelectDayPhase(& self.displayConfig, & self.vehicleData, hour, self.dayPhase)
+    }
+
+    // 'Auto' follows the day phase, every other value names a palette
+    fn PaletteName(& self) -> String {
+        match (self.displayConfig.theme.as_str(), self.dayPhase) {
+            ("Auto", display::DayPhase::Day) => "day".to_owned(),
+            ("Auto", display::DayPhase::Night) => "night".to_owned(),
+            (name, _) => name.to_owned(),
+        }
+    }
+
+    fn TransitionTime(& self) -> Duration {
+        Duration::from_millis((self.displayConfig.rampTime * 1000.0) as u64)
+    }
+
+    // Switches brightness and palette when the day phase changes
+    fn UpdateDayPhase(& mut self, ctx : & mut AppContext, dt : Duration) {
+
+        let phase = self.SelectDayPhase();
+
+        if phase != self.dayPhase {
+            println!("Switching to {:?} profile", phase);
+            self.dayPhase = phase;
+
+            let transitionTime = self.TransitionTime();
+
+            if let Some(ref mut backlight) = self.backlight {
+                backlight.SetTarget(display::BacklightLevel(& self.displayConfig, phase), transitionTime);
+            }
+
+            let palette = self.PaletteName();
+            if let Err(err) = self.theme.SetPalette(& palette, transitionTime) {
+                println!("{}", err);
+            }
+        }
+
+        let result = match self.backlight {
+            Some(ref mut backlight) => backlight.Update(dt),
+            None => Ok(()),
+        };
+
+        if let Err(err) = result {
+            println!("Backlight control disabled. {}", err);
+            self.backlight = None;
+        }
+
+        // during the cross-fade all themed colors are re-uploaded every frame
+        if self.theme.Update(dt) {
+            self.themedQuads.Refresh(& self.theme);
+            if let Some(ref mut speedometer) = self.speedometer {
+                speedometer.Refresh(& self.theme, self.displayedSpeed);
+            }
+            for chart in self.charts.iter_mut() {
+                chart.SetColors(ThemeChartColors(& self.theme));
+            }
+            ctx.render.clearColor = self.theme.Color("background");
+            ctx.scheduler.RequestRedraw();
+
+            if let Some(ref warnings) = self.warnings {
+                warnings.Send(WarningMessage::SetColor(self.theme.Color("warning")));
+            }
+        }
+    }
+
+    fn SpeedGuardActive(& self) -> bool {
+        vehicle::CalculateDrivingSpeed(& self.config, & self.vehicleData) > self.displayConfig.pageGuardSpeed
+    }
+
+    // Swipes go through the pages, swiping down returns from a page opened
+    // on top. Tapping the power history opens the diagnostics.
+    fn HandleGesture(& mut self, gesture : Gesture) {
+
+        // no fiddling with the dashboard while riding
+        if self.SpeedGuardActive() {
+            return;
+        }
+
+        let result = match gesture {
+            Gesture::Swipe { direction: SwipeDirection::Left, .. } => { self.pages.Next(Transition::SlideLeft); Ok(()) },
+            Gesture::Swipe { direction: SwipeDirection::Right, .. } => { self.pages.Previous(Transition::SlideRight); Ok(()) },
+            Gesture::Swipe { direction: SwipeDirection::Down, .. } => { self.pages.Pop(Transition::SlideDown); Ok(()) },
+            Gesture::Swipe { direction: SwipeDirection::Up, .. } => Ok(()),
+            Gesture::Tap { x, y } => {
+                let rect = CHARTS[POWER_CHART].rect;
+                let onChart = x >= rect[0] && x <= rect[0] + rect[2] && y >= rect[1] && y <= rect[1] + rect[3];
+
+                if onChart && self.pages.Current() == Some(RIDING_PAGE) {
+                    self.pages.Push(DIAGNOSTICS_PAGE, Transition::SlideUp)
+                } else {
+                    Ok(())
+                }
+            },
+        };
+
+        if let Err(err) = result {
+            println!("{}", err);
+        }
+    }
+
+    // Tells the warning layer when the warning changes, it animates the bar
+    // on its own thread.
+    fn UpdateWarning(& mut self) {
+
+        let lowBattery = self.vehicleData.batteryCharge < LOW_BATTERY_THRESHOLD;
+        if lowBattery == self.lowBatteryShown {
+            return;
+        }
+
+        if let Some(ref warnings) = self.warnings {
+            warnings.Send(WarningMessage::LowBattery(lowBattery));
+        }
+        self.lowBatteryShown = lowBattery;
+    }
+
+    // Loads the next part of the dashboard, called once per frame until done.
+    fn LoadNextStage(& mut self, ctx : & mut AppContext) -> ebola::Result<()> {
+
+        match self.loadingStage {
+            LoadingStage::Splash => {
+                // wait until the splash actually made it to the screen
+                if self.splashRendered {
+                    self.bootLog.Mark("splash shown");
+                    self.pendingRender = Some(RenderContext::new(self.theme.Color("background")));
+                    self.loadingStage = LoadingStage::World;
+                }
+            },
+            LoadingStage::World => {
+                // decoding and packing run in the background while the world
+                // pass loads
+                self.atlasImages = Some(PackAtlasImages());
+
+                let pass = PrepareWorldPass(& mut ctx.assets)?;
+                self.pendingRender.as_mut().unwrap().AddPass(pass);
+                self.loadingStage = LoadingStage::UI;
+            },
+            LoadingStage::UI => {
+                let packedAtlas = match self.atlasImages.as_ref().unwrap().try_recv() {
+                    Ok(result) => result?,
+                    // keep showing the splash until the atlas is packed
+                    Err(TryRecvError::Empty) => return Ok(()),
+                    Err(TryRecvError::Disconnected) => {
+                        let err = io::Error::new(io::ErrorKind::Other, "image decoding thread died");
+                        return Err(ebola::Error::Io(DATA_PATH.to_owned(), err));
+                    }
+                };
+                self.atlasImages = None;
+
+                let mut renderContext = self.pendingRender.take().unwrap();
+                renderContext.AddPass(PrepareUIPass(& mut ctx.assets, packedAtlas, & self.theme, & mut self.themedQuads)?);
+
+                let speedometer = Speedometer::new(& self.theme, self.displayedSpeed);
+                renderContext.AddPass(speedometer.Pass(& mut ctx.assets)?);
+                self.speedometer = Some(speedometer);
+
+                for definition in CHARTS.iter() {
+                    let chart = PrepareChart(& mut ctx.assets, & self.theme, definition)?;
+                    renderContext.AddPass(chart.Pass(definition.pass, CHART_PASS_ORDER));
+                    self.charts.push(chart);
+                }
+
+                // hides the passes of the other pages with the next update
+                let mut ridingPasses = vec![ "world", "ui", "speedometer" ];
+                ridingPasses.extend(ChartPasses(RIDING_PAGE));
+                self.pages.AddPage(RIDING_PAGE, & ridingPasses);
+                self.pages.AddPage(DIAGNOSTICS_PAGE, & ChartPasses(DIAGNOSTICS_PAGE));
+                // the background may have faded while loading
+                renderContext.clearColor = self.theme.Color("background");
+
+                ctx.render = renderContext;
+                ctx.scheduler.RequestRedraw();
+                self.loadingStage = LoadingStage::Done;
+
+                if self.showStats {
+                    if let Err(err) = self.PrepareStatsOverlay(ctx) {
+                        println!("Failed to prepare stats overlay. {}", err);
+                    }
+                }
+
+                self.bootLog.Mark("dashboard loaded");
+            },
+            LoadingStage::Done => (),
+        }
+
+        Ok(())
+    }
+
+    fn PrepareStatsOverlay(& mut self, ctx : & mut AppContext) -> ebola::Result<()> {
+        let shader = ctx.assets.GetShaderVariant("default", &["VERTEX_COLOR"])?;
+        let budget = Duration::from_micros(1_000_000 / TARGET_FPS as u64);
+
+        let (overlay, pass) = StatsOverlay::new(& shader, [10.0, 10.0, 240.0, 60.0], 60, budget, STATS_PASS_ORDER)?;
+        ctx.render.AddPass(pass);
+        self.statsOverlay = Some(overlay);
+
+        Ok(())
+    }
+}
+
+impl Application for Dashboard {
+
+    fn init(& mut self, ctx : & mut AppContext) -> ebola::Result<()> {
+        self.backlight = match Backlight::Open(& self.displayConfig.backlightPath) {
+            Ok(backlight) => Some(backlight),
+            Err(err) => {
+                println!("Backlight control disabled. {}", err);
+                None
+            }
+        };
+
+        // ramp from the level the bootloader left to the current profile
+        self.dayPhase = self.SelectDayPhase();
+        let level = display::BacklightLevel(& self.displayConfig, self.dayPhase);
+        let transitionTime = self.TransitionTime();
+        if let Some(ref mut backlight) = self.backlight {
+            backlight.SetTarget(level, transitionTime);
+        }
+
+        self.theme = Theme::LoadDirectory(& ctx.assets.FullPath("themes"), & self.PaletteName(), DefaultPalette());
+
+        ctx.render = match PrepareSplashScreen(& mut ctx.assets, & self.theme, & mut self.themedQuads) {
+            Ok(renderContext) => renderContext,
+            Err(err) => {
+                println!("Failed to prepare splash screen. {}", err);
+                SafeScreen()
+            }
+        };
+
+        let warningColor = self.theme.Color("warning");
+        self.warnings = match layer::SpawnLayer(WarningLayerDescriptor(), DATA_PATH, TARGET_FPS, move || WarningLayer::new(warningColor)) {
+            Ok(warnings) => Some(warnings),
+            Err(err) => {
+                println!("Failed to create warning layer. {}", err);
+                None
+            }
+        };
+
+        Ok(())
+    }
+
+    fn update(& mut self, ctx : & mut AppContext, dt : Duration) {
+        if let Err(err) = self.LoadNextStage(ctx) {
+            println!("Failed to prepare UI, falling back to safe screen. {}", err);
+            ctx.render = SafeScreen();
+            ctx.scheduler.RequestRedraw();
+            self.pendingRender = None;
+            self.atlasImages = None;
+            self.loadingStage = LoadingStage::Done;
+        }
+
+        self.UpdateDayPhase(ctx, dt);
+
+        // only redraw when the needle moves, which happens every frame while
+        // the smoother catches up and stops once it settled
+        self.speedSmoother.SetTarget(vehicle::CalculateDrivingSpeed(& self.config, & self.vehicleData));
+        self.speedSmoother.Update(dt);
+        if ctx.scheduler.Track(& mut self.displayedSpeed, self.speedSmoother.Value()) {
+            if let Some(ref mut speedometer) = self.speedometer {
+                speedometer.SetSpeed(& self.theme, self.displayedSpeed);
+            }
+        }
+
+        // hidden charts keep recording, but don't cause redraws
+        for (chart, definition) in self.charts.iter_mut().zip(CHARTS.iter()) {
+            let value = vehicle::ReadSignal(& self.config, & self.vehicleData, definition.signal);
+            let visible = ctx.render.Pass(definition.pass).map_or(false, |pass| pass.enabled);
+            if chart.AddSample(dt, value) && visible {
+                ctx.scheduler.RequestRedraw();
+            }
+        }
+
+        self.UpdateWarning();
+
+        // nothing but the riding page while moving
+        if self.SpeedGuardActive() && self.pages.Current().map_or(false, |page| page != RIDING_PAGE) {
+            println!("Faster than {} km/h, returning to the riding page", self.displayConfig.pageGuardSpeed);
+            if let Err(err) = self.pages.ResetTo(RIDING_PAGE, Transition::SlideRight) {
+                println!("{}", err);
+            }
+        }
+
+        if self.pages.Update(dt, & mut ctx.render) {
+            ctx.scheduler.RequestRedraw();
+        }
+    }
+
+    fn render(& mut self, ctx : & mut AppContext) {
+        self.splashRendered = true;
+
+        for (chart, definition) in self.charts.iter_mut().zip(CHARTS.iter()) {
+            if let Some(pass) = ctx.render.PassMut(definition.pass) {
+                if !pass.enabled {
+                    continue;
+                }
+                if let Err(err) = chart.Render(pass) {
+                    println!("Failed to update chart. {}", err);
+                }
+            }
+        }
+
+        if let Some(ref overlay) = self.statsOverlay {
+            overlay.Update(& ctx.stats);
+        }
+
+        if self.showStats && self.lastStatsLog.elapsed() >= Duration::from_secs(STATS_LOG_INTERVAL_SECS) {
+            println!("Stats: {}", ctx.stats.Summary());
+            self.lastStatsLog = Instant::now();
+        }
+    }
+
+    fn handle_event(& mut self, _ctx : & mut AppContext, event : & Event) {
+        match *event {
+            Event::Quit => println!("Shutdown requested"),
+            Event::Touch(_) => (),
+            Event::Gesture(gesture) => self.HandleGesture(gesture),
+        }
+    }
+
+    fn shutdown(& mut self, _ctx : & mut AppContext) {
+        // the layer thread has to exit before the EGL display is terminated
+        self.warnings = None;
+
+        // GL objects have to be deleted while the context is alive
+        self.charts.clear();
+        self.speedometer = None;
+        self.statsOverlay = None;
+        self.pendingRender = None;
+    }
+}
+
+// Runs the tool with a GL context, which is needed to compile shaders and
+// query extensions. Returns false if the context couldn't be created.
+fn WithGLContext<F : FnOnce() -> bool>(tool : F) -> bool {
+
+    bcm_host::init();
+
+    let mut window = match ebola::CreateRenderWindow() {
+        Ok(window) => window,
+        Err(err) => {
+            println!("{}", err);
+            return false;
+        }
+    };
+
+    // the window has to outlive the context
+    let _glContext = match ebola::InitEGL(& mut window) {
+        Ok(context) => context,
+        Err(err) => {
+            println!("{}", err);
+            return false;
+        }
+    };
+
+    tool()
+}
+
+// Prints the result per file, returns whether all succeeded.
+fn ReportResults(results : ebola::Result<Vec<(String, ebola::Result<()>)>>) -> bool {
+
+    let results = match results {
+        Ok(results) => results,
+        Err(err) => {
+            println!("{}", err);
+            return false;
+        }
+    };
+
+    let mut success = true;
+
+    for (path, result) in results {
+        match result {
+            Ok(()) => println!("OK     {}", path),
+            Err(err) => {
+                println!("FAILED {}\n{}", path, err);
+                success = false;
+            }
+        }
+    }
+
+    success
+}
+
+// Compiles all shaders of the data directory and reports the failures.
+// Used by deploy.sh to reject broken shaders before they reach the bike.
+fn ValidateShaders(dataPath : & str) -> bool {
+    WithGLContext(|| ReportResults(renderer::ValidateShaderDirectory(dataPath)))
+}
+
+// Converts the images and shader variants of the data directory into the
+// fast-load cache. Run by deploy.sh on the device, as ETC1 support and the
+// program binaries depend on the GPU.
+fn BuildAssetCache(dataPath : & str, compress : bool) -> bool {
+    WithGLContext(|| ReportResults(cache::BuildAssetCache(dataPath, compress)))
 }
 
 fn main() {
     
+    let args : Vec<String> = env::args().collect();
+    
+    if args.len() > 1 && args[1] == "--validate-shaders" {
+        let dataPath = if args.len() > 2 { args[2].clone() } else { DATA_PATH.to_owned() };
+        process::exit(if ValidateShaders(& dataPath) { 0 } else { 1 });
+    }
+
+    // --build-asset-cache [dir] [--etc1]
+    if args.len() > 1 && args[1] == "--build-asset-cache" {
+        let dataPath = if args.len() > 2 && args[2] != "--etc1" { args[2].clone() } else { DATA_PATH.to_owned() };
+        let compress = args.iter().any(|arg| arg == "--etc1");
+        process::exit(if BuildAssetCache(& dataPath, compress) { 0 } else { 1 });
+    }
+
+    let showStats = args.iter().any(|arg| arg == "--stats");
+
+    // the display comes up first, everything else is loaded while the
+    // splash screen is shown
+    let bootLog = boot::BootLog::new();
+
+    bcm_host::init();
+       
+    let mut window = match ebola::CreateRenderWindow() {
+        Ok(window) => window,
+        Err(err) => {
+            println!("{}", err);
+            process::exit(1);
+        }
+    };
+
+    let glContext = match ebola::InitEGL(&mut window) {
+        Ok(context) => context,
+        Err(err) => {
+            println!("{}", err);
+            process::exit(1);
+        }
+    };
+
+    // the dashboard values change only a few times per second, vsync
+    // and the frame limit keep the Pi from rendering more than needed
+    if let Err(err) = ebola::SetSwapInterval(& glContext, 1) {
+        println!("{}", err);
+    }
+
+    bootLog.Mark("egl ready");
+
     let config = vehicle::LoadVehicleConfiguration(& GetFullDataPath("test_vehicle.cfg"));
     println!("GearRatio: {}", config.gearRatio);
     println!("DriveWheelDiameter: {}", config.driveWheelDiameter);
 
     let mut vehicleData = vehicle::VehicleData::new();
 
-
     let rpms = [2000, 3000, 4000, 5000, 6000];
     for rpm in rpms.iter() {
         vehicleData.engineRPM = *rpm;
@@ -122,18 +1073,21 @@ fn main() {
         println!("Speed at {} rpm: {} km/h", rpm, speed);
     }
 
-    bcm_host::init();
-       
-    let mut window = ebola::CreateRenderWindow();
+    let displayConfig = display::LoadDisplayConfiguration(& GetFullDataPath("display.cfg"));
 
-    let glContext = ebola::InitEGL(&mut window);
+    bootLog.Mark("config loaded");
 
-    let (uiStage, uiRenderCommands) = PrepareUIStage();
+    let mut appContext = AppContext::new(SafeScreen(), FrameScheduler::new(TARGET_FPS), AssetManager::new(DATA_PATH));
 
-    ebola::RunMainLoop(RenderContext  {
-                                    shaderStages: vec![uiStage],
-                                    clearColor: [1.0, 0.0, 0.0, 1.0],
-                                    renderCommands: vec![uiRenderCommands],
-                                },
-                       glContext);
+    match TouchScreen::Open(& displayConfig.touchDevice, displayConfig.touchSize, SCREEN_SIZE) {
+        Ok(touchScreen) => appContext.SetTouchScreen(touchScreen, GestureRecognizer::new()),
+        Err(err) => println!("Touch input disabled. {}", err),
+    }
+
+    let mut dashboard = Dashboard::new(config, vehicleData, displayConfig, showStats, bootLog);
+
+    if let Err(err) = RunApplication(& mut dashboard, glContext, appContext) {
+        println!("{}", err);
+        process::exit(1);
+    }
 }
\ No newline at end of file
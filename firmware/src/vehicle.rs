@@ -75,6 +75,14 @@ pub struct VehicleData {
     pub turnSignal : TurnSignalStatus,
     // indicates whether full beam is activated or not
     pub fullBeamActive : bool,
+    // indicates whether low beam is activated or not
+    pub lowBeamActive : bool,
+    // ambient light in lux, None if there is no sensor reading
+    pub ambientLight : Option<f32>,
+    // electrical power of the motor in kW, negative while regenerating
+    pub motorPower : f32,
+    // temperature of the motor in °C
+    pub motorTemperature : f32,
 }
 
 impl VehicleData {
@@ -86,6 +94,10 @@ impl VehicleData {
             batteryCharge: 100,
             turnSignal: TurnSignalStatus::Off,
             fullBeamActive: false,
+            lowBeamActive: false,
+            ambientLight: None,
+            motorPower: 0.0,
+            motorTemperature: 20.0,
         }
     }
 
@@ -97,4 +109,25 @@ pub fn CalculateDrivingSpeed(config : & VehicleConfiguration, data : & VehicleDa
     let wheelRPM = data.engineRPM as f32 * config.gearRatio;
     let meterPerMin = wheelRPM * config.driveWheelDiameter;
     ((meterPerMin * 60.0)/1000.0)
-}
\ No newline at end of file
+}
+// Values that can be plotted over time
+#[derive(Clone, Copy)]
+pub enum Signal {
+    // km/h
+    Speed,
+    // kW
+    Power,
+    // °C
+    MotorTemperature,
+    // percent
+    BatteryCharge,
+}
+
+pub fn ReadSignal(config : & VehicleConfiguration, data : & VehicleData, signal : Signal) -> f32 {
+    match signal {
+        Signal::Speed => CalculateDrivingSpeed(config, data),
+        Signal::Power => data.motorPower,
+        Signal::MotorTemperature => data.motorTemperature,
+        Signal::BatteryCharge => data.batteryCharge as f32,
+    }
+}